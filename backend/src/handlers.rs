@@ -0,0 +1,624 @@
+use crate::storage::{
+    is_valid_environment, unix_now, EncryptedData, RotatedSecret, ShareData, Storage, StorageError,
+    DEFAULT_ENVIRONMENT,
+};
+use actix_web::http::header::{ETAG, IF_MATCH};
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use serde::Deserialize;
+use std::sync::Arc;
+
+#[derive(Clone)]
+pub struct AppState {
+    pub storage: Arc<dyn Storage>,
+}
+
+#[derive(Deserialize)]
+struct StoreData {
+    project_id: String,
+    encrypted_content: String,
+}
+
+#[derive(Deserialize)]
+struct RotateKey {
+    /// Every environment of the project, re-encrypted under the new key.
+    secrets: Vec<RotatedSecret>,
+    /// Whether to move the project to a freshly generated access key.
+    #[serde(default)]
+    rotate_access_key: bool,
+    /// An access key to move the project to, picked by the client so it can keep it before
+    /// the rotation goes through.
+    #[serde(default)]
+    new_access_key: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct SetBase {
+    /// The environment the project's other environments are layered over; none if omitted.
+    #[serde(default)]
+    base: Option<String>,
+}
+
+/// Shortest access key a client may pick; generated ones are UUIDs.
+const MIN_ACCESS_KEY_LEN: usize = 32;
+
+#[derive(Deserialize)]
+struct StoreShare {
+    project_id: String,
+    share_code: String,
+    encrypted_content: String,
+    /// Seconds until the share expires; it never expires if omitted.
+    expires_in: Option<u64>,
+    /// How many times the share can be claimed; once if omitted.
+    max_uses: Option<u32>,
+}
+
+/// Renders a secret version as a strong entity tag.
+fn etag(version: u64) -> String {
+    format!("\"{}\"", version)
+}
+
+/// Parses the `If-Match` header into the version the client last saw.
+///
+/// `Ok(None)` means the update is unconditional (no header, or `*`).
+fn expected_version(req: &HttpRequest) -> Result<Option<u64>, ()> {
+    let value = match req.headers().get(IF_MATCH) {
+        Some(value) => value.to_str().map_err(|_| ())?.trim(),
+        None => return Ok(None),
+    };
+    if value == "*" {
+        return Ok(None);
+    }
+    value
+        .trim_start_matches("W/")
+        .trim_matches('"')
+        .parse()
+        .map(Some)
+        .map_err(|_| ())
+}
+
+/// Reads the environment a request is about from the `X-Environment` header.
+///
+/// `Ok(None)` means the header is absent; `Err` means it isn't a valid environment name.
+fn requested_environment(req: &HttpRequest) -> Result<Option<&str>, ()> {
+    match req.headers().get("X-Environment") {
+        Some(value) => match value.to_str() {
+            Ok(name) if is_valid_environment(name) => Ok(Some(name)),
+            _ => Err(()),
+        },
+        None => Ok(None),
+    }
+}
+
+fn invalid_environment() -> HttpResponse {
+    HttpResponse::BadRequest().json(serde_json::json!({
+        "detail": "Invalid X-Environment header, use letters, digits, '-', '_' or '.'"
+    }))
+}
+
+async fn share_config(data: web::Json<StoreShare>, state: web::Data<AppState>) -> impl Responder {
+    if data.max_uses == Some(0) {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "detail": "max_uses must be at least 1"
+        }));
+    }
+
+    let share_data = ShareData {
+        project_id: data.project_id.clone(),
+        encrypted_content: data.encrypted_content.clone(),
+        share_code: data.share_code.clone(),
+        expires_at: data
+            .expires_in
+            .map(|secs| unix_now().saturating_add(secs.min(i64::MAX as u64) as i64)),
+        remaining_uses: data.max_uses.unwrap_or(1),
+    };
+
+    match state.storage.insert_share(share_data).await {
+        Ok(_) => HttpResponse::Ok().json(serde_json::json!({
+            "message": "Data stored successfully.",
+        })),
+        Err(StorageError::AlreadyExists) => HttpResponse::BadRequest().json(serde_json::json!({
+            "detail": "Data already exists, use update if you want to modify it"
+        })),
+        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "detail": "Failed to store data"
+        })),
+    }
+}
+
+// retrieve data using share code as parameter | ShareData
+async fn pull_config(req: HttpRequest, state: web::Data<AppState>) -> impl Responder {
+    let share_code = req
+        .headers()
+        .get("X-Share-Code")
+        .and_then(|h| h.to_str().ok());
+    let share_code = match share_code {
+        Some(code) => code,
+        None => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "detail": "Missing Share Code"
+            }))
+        }
+    };
+
+    let project_id = req
+        .headers()
+        .get("X-Project-Id")
+        .and_then(|h| h.to_str().ok());
+    let project_id = match project_id {
+        Some(id) => id,
+        None => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "detail": "Missing Project Id"
+            }))
+        }
+    };
+
+    // The record is deleted once its last use is claimed
+    match state.storage.claim_share(share_code, project_id).await {
+        Ok(Some(data)) => HttpResponse::Ok().json(serde_json::json!({
+            "encrypted_content": data.encrypted_content
+        })),
+        Ok(None) => HttpResponse::NotFound().json(serde_json::json!({
+            "detail": "Share not found or expired"
+        })),
+        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "detail": "Failed to retrieve data"
+        })),
+    }
+}
+
+// Creates a project, or adds an environment to the project of the given X-API-Key
+async fn store_data(
+    req: HttpRequest,
+    data: web::Json<StoreData>,
+    state: web::Data<AppState>,
+) -> impl Responder {
+    let environment = match requested_environment(&req) {
+        Ok(environment) => environment.unwrap_or(DEFAULT_ENVIRONMENT),
+        Err(_) => return invalid_environment(),
+    };
+
+    let api_key = req.headers().get("X-API-Key").and_then(|h| h.to_str().ok());
+    let access_key = match api_key {
+        Some(key) => match state.storage.list_environments(key).await {
+            Ok(environments) if !environments.is_empty() => key.to_string(),
+            Ok(_) => {
+                return HttpResponse::NotFound().json(serde_json::json!({
+                    "detail": "Data not found"
+                }))
+            }
+            Err(_) => {
+                return HttpResponse::InternalServerError().json(serde_json::json!({
+                    "detail": "Failed to store data"
+                }))
+            }
+        },
+        None => uuid::Uuid::new_v4().to_string(),
+    };
+    let new_data = EncryptedData {
+        project_id: data.project_id.clone(),
+        environment: environment.to_string(),
+        encrypted_content: data.encrypted_content.clone(),
+        access_key: access_key.clone(),
+        version: 1,
+    };
+
+    match state.storage.insert_secret(new_data).await {
+        Ok(_) => HttpResponse::Ok()
+            .insert_header((ETAG, etag(1)))
+            .json(serde_json::json!({
+                "message": "Data stored successfully",
+                "access_key": access_key
+            })),
+        Err(StorageError::AlreadyExists) => HttpResponse::BadRequest().json(serde_json::json!({
+            "detail": "Data already exists, use update if you want to modify it"
+        })),
+        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "detail": "Failed to store data"
+        })),
+    }
+}
+
+async fn retrieve_data(req: HttpRequest, state: web::Data<AppState>) -> impl Responder {
+    let api_key = req.headers().get("X-API-Key").and_then(|h| h.to_str().ok());
+    let api_key = match api_key {
+        Some(key) => key,
+        None => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "detail": "Missing API Key"
+            }))
+        }
+    };
+
+    let environment = match requested_environment(&req) {
+        Ok(environment) => environment.unwrap_or(DEFAULT_ENVIRONMENT),
+        Err(_) => return invalid_environment(),
+    };
+
+    let base = match state.storage.find_base(api_key).await {
+        Ok(base) => base,
+        Err(_) => {
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "detail": "Failed to retrieve data"
+            }))
+        }
+    };
+
+    match state.storage.find_secret(api_key, environment).await {
+        Ok(Some(data)) => HttpResponse::Ok()
+            .insert_header((ETAG, etag(data.version)))
+            .json(serde_json::json!({
+                "encrypted_content": data.encrypted_content,
+                "base": base
+            })),
+        Ok(None) => HttpResponse::NotFound().json(serde_json::json!({
+            "detail": "Data not found"
+        })),
+        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "detail": "Failed to retrieve data"
+        })),
+    }
+}
+
+async fn update_data(
+    req: HttpRequest,
+    data: web::Json<StoreData>,
+    state: web::Data<AppState>,
+) -> impl Responder {
+    let api_key = req.headers().get("X-API-Key").and_then(|h| h.to_str().ok());
+    let api_key = match api_key {
+        Some(key) => key,
+        None => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "detail": "Missing API Key"
+            }))
+        }
+    };
+
+    let expected_version = match expected_version(&req) {
+        Ok(version) => version,
+        Err(_) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "detail": "Invalid If-Match header"
+            }))
+        }
+    };
+
+    let environment = match requested_environment(&req) {
+        Ok(environment) => environment.unwrap_or(DEFAULT_ENVIRONMENT),
+        Err(_) => return invalid_environment(),
+    };
+
+    match state
+        .storage
+        .update_secret(
+            api_key,
+            environment,
+            &data.encrypted_content,
+            expected_version,
+        )
+        .await
+    {
+        Ok(Some(version)) => HttpResponse::Ok()
+            .insert_header((ETAG, etag(version)))
+            .json(serde_json::json!({
+                "message": "Data updated successfully",
+                "version": version
+            })),
+        Ok(None) => HttpResponse::NotFound().json(serde_json::json!({
+            "detail": "Data not found"
+        })),
+        Err(StorageError::VersionMismatch(current)) => HttpResponse::PreconditionFailed()
+            .insert_header((ETAG, etag(current)))
+            .json(serde_json::json!({
+                "detail": "Data was modified since it was last pulled",
+                "version": current
+            })),
+        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "detail": "Failed to update data"
+        })),
+    }
+}
+
+async fn delete_data(req: HttpRequest, state: web::Data<AppState>) -> impl Responder {
+    let api_key = req.headers().get("X-API-Key").and_then(|h| h.to_str().ok());
+    let api_key = match api_key {
+        Some(key) => key,
+        None => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "detail": "Missing API Key"
+            }))
+        }
+    };
+
+    // Without an X-Environment header the whole project is deleted
+    let environment = match requested_environment(&req) {
+        Ok(environment) => environment,
+        Err(_) => return invalid_environment(),
+    };
+
+    match state.storage.delete_secret(api_key, environment).await {
+        Ok(true) => HttpResponse::Ok().json(serde_json::json!({
+            "message": "Data deleted successfully"
+        })),
+        Ok(false) => HttpResponse::NotFound().json(serde_json::json!({
+            "detail": "Data not found"
+        })),
+        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "detail": "Failed to delete data"
+        })),
+    }
+}
+
+// Re-encrypts every environment of a project at once, optionally with a new access key
+async fn rotate_key(
+    req: HttpRequest,
+    data: web::Json<RotateKey>,
+    state: web::Data<AppState>,
+) -> impl Responder {
+    let api_key = req.headers().get("X-API-Key").and_then(|h| h.to_str().ok());
+    let api_key = match api_key {
+        Some(key) => key,
+        None => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "detail": "Missing API Key"
+            }))
+        }
+    };
+
+    let data = data.into_inner();
+    let mut environments: Vec<&str> = data
+        .secrets
+        .iter()
+        .map(|s| s.environment.as_str())
+        .collect();
+    environments.sort();
+    environments.dedup();
+    if environments.len() != data.secrets.len()
+        || !environments.iter().all(|e| is_valid_environment(e))
+    {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "detail": "Each environment must be listed once with a valid name"
+        }));
+    }
+
+    let new_access_key = match data.new_access_key.clone() {
+        Some(key) => {
+            if key.len() < MIN_ACCESS_KEY_LEN
+                || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+            {
+                return HttpResponse::BadRequest().json(serde_json::json!({
+                    "detail": "The new access key must be at least 32 letters, digits or dashes"
+                }));
+            }
+            match state.storage.list_environments(&key).await {
+                Ok(environments) if environments.is_empty() => Some(key),
+                Ok(_) => {
+                    return HttpResponse::Conflict().json(serde_json::json!({
+                        "detail": "The new access key is already in use"
+                    }))
+                }
+                Err(_) => {
+                    return HttpResponse::InternalServerError().json(serde_json::json!({
+                        "detail": "Failed to rotate key"
+                    }))
+                }
+            }
+        }
+        None => data
+            .rotate_access_key
+            .then(|| uuid::Uuid::new_v4().to_string()),
+    };
+    let versions: serde_json::Map<String, serde_json::Value> = data
+        .secrets
+        .iter()
+        .map(|s| (s.environment.clone(), (s.expected_version + 1).into()))
+        .collect();
+
+    match state
+        .storage
+        .rotate_secrets(api_key, data.secrets, new_access_key.as_deref())
+        .await
+    {
+        Ok(true) => HttpResponse::Ok().json(serde_json::json!({
+            "message": "Key rotated successfully",
+            "access_key": new_access_key.as_deref().unwrap_or(api_key),
+            "versions": versions
+        })),
+        Ok(false) => HttpResponse::NotFound().json(serde_json::json!({
+            "detail": "Data not found"
+        })),
+        Err(StorageError::VersionMismatch(current)) => {
+            HttpResponse::PreconditionFailed().json(serde_json::json!({
+                "detail": "Data was modified since it was last pulled",
+                "version": current
+            }))
+        }
+        Err(StorageError::EnvironmentMismatch) => {
+            HttpResponse::PreconditionFailed().json(serde_json::json!({
+                "detail": "Environments were added or removed since they were pulled"
+            }))
+        }
+        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "detail": "Failed to rotate key"
+        })),
+    }
+}
+
+async fn list_versions(req: HttpRequest, state: web::Data<AppState>) -> impl Responder {
+    let api_key = req.headers().get("X-API-Key").and_then(|h| h.to_str().ok());
+    let api_key = match api_key {
+        Some(key) => key,
+        None => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "detail": "Missing API Key"
+            }))
+        }
+    };
+
+    let environment = match requested_environment(&req) {
+        Ok(environment) => environment.unwrap_or(DEFAULT_ENVIRONMENT),
+        Err(_) => return invalid_environment(),
+    };
+
+    match state.storage.list_versions(api_key, environment).await {
+        Ok(Some(versions)) => {
+            let versions: Vec<_> = versions
+                .iter()
+                .map(|v| {
+                    serde_json::json!({
+                        "version": v.version,
+                        "created_at": v.created_at,
+                        "content_hash": v.content_hash,
+                    })
+                })
+                .collect();
+            HttpResponse::Ok().json(serde_json::json!({ "versions": versions }))
+        }
+        Ok(None) => HttpResponse::NotFound().json(serde_json::json!({
+            "detail": "Data not found"
+        })),
+        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "detail": "Failed to retrieve versions"
+        })),
+    }
+}
+
+async fn retrieve_version(
+    req: HttpRequest,
+    version: web::Path<u64>,
+    state: web::Data<AppState>,
+) -> impl Responder {
+    let api_key = req.headers().get("X-API-Key").and_then(|h| h.to_str().ok());
+    let api_key = match api_key {
+        Some(key) => key,
+        None => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "detail": "Missing API Key"
+            }))
+        }
+    };
+
+    let environment = match requested_environment(&req) {
+        Ok(environment) => environment.unwrap_or(DEFAULT_ENVIRONMENT),
+        Err(_) => return invalid_environment(),
+    };
+
+    match state
+        .storage
+        .find_version(api_key, environment, *version)
+        .await
+    {
+        Ok(Some(v)) => HttpResponse::Ok().json(serde_json::json!({
+            "version": v.version,
+            "created_at": v.created_at,
+            "content_hash": v.content_hash,
+            "encrypted_content": v.encrypted_content
+        })),
+        Ok(None) => HttpResponse::NotFound().json(serde_json::json!({
+            "detail": "Version not found"
+        })),
+        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "detail": "Failed to retrieve version"
+        })),
+    }
+}
+
+async fn list_environments(req: HttpRequest, state: web::Data<AppState>) -> impl Responder {
+    let api_key = req.headers().get("X-API-Key").and_then(|h| h.to_str().ok());
+    let api_key = match api_key {
+        Some(key) => key,
+        None => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "detail": "Missing API Key"
+            }))
+        }
+    };
+
+    let environments = match state.storage.list_environments(api_key).await {
+        Ok(environments) if !environments.is_empty() => environments,
+        Ok(_) => {
+            return HttpResponse::NotFound().json(serde_json::json!({
+                "detail": "Data not found"
+            }))
+        }
+        Err(_) => {
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "detail": "Failed to retrieve environments"
+            }))
+        }
+    };
+
+    match state.storage.find_base(api_key).await {
+        Ok(base) => HttpResponse::Ok().json(serde_json::json!({
+            "environments": environments,
+            "base": base
+        })),
+        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "detail": "Failed to retrieve environments"
+        })),
+    }
+}
+
+// Declares the environment a project's other environments are layered over, or removes it
+async fn set_base(
+    req: HttpRequest,
+    data: web::Json<SetBase>,
+    state: web::Data<AppState>,
+) -> impl Responder {
+    let api_key = req.headers().get("X-API-Key").and_then(|h| h.to_str().ok());
+    let api_key = match api_key {
+        Some(key) => key,
+        None => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "detail": "Missing API Key"
+            }))
+        }
+    };
+
+    let environments = match state.storage.list_environments(api_key).await {
+        Ok(environments) if !environments.is_empty() => environments,
+        Ok(_) => {
+            return HttpResponse::NotFound().json(serde_json::json!({
+                "detail": "Data not found"
+            }))
+        }
+        Err(_) => {
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "detail": "Failed to set the base environment"
+            }))
+        }
+    };
+    if let Some(base) = data.base.as_deref() {
+        if !environments.iter().any(|environment| environment == base) {
+            return HttpResponse::NotFound().json(serde_json::json!({
+                "detail": format!("Environment '{}' not found", base)
+            }));
+        }
+    }
+
+    match state.storage.set_base(api_key, data.base.as_deref()).await {
+        Ok(()) => HttpResponse::Ok().json(serde_json::json!({
+            "message": "Base environment updated successfully",
+            "base": data.base
+        })),
+        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "detail": "Failed to set the base environment"
+        })),
+    }
+}
+
+/// Registers every API route on an actix `App`.
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.route("/push", web::post().to(store_data))
+        .route("/pull", web::get().to(retrieve_data))
+        .route("/update", web::put().to(update_data))
+        .route("/delete", web::delete().to(delete_data))
+        .route("/environments", web::get().to(list_environments))
+        .route("/base", web::put().to(set_base))
+        .route("/rotate", web::post().to(rotate_key))
+        .route("/versions", web::get().to(list_versions))
+        .route("/versions/{version}", web::get().to(retrieve_version))
+        .route("/share", web::post().to(share_config))
+        .route("/share", web::get().to(pull_config));
+}
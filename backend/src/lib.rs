@@ -0,0 +1,4 @@
+pub mod handlers;
+pub mod storage;
+
+pub use handlers::{configure, AppState};
@@ -0,0 +1,296 @@
+use super::{
+    unix_now, EncryptedData, RotatedSecret, SecretVersion, ShareData, Storage, StorageError,
+};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+
+/// Keeps everything in process memory. Nothing survives a restart, so this is meant for tests.
+#[derive(Default)]
+pub struct MemoryStorage {
+    inner: Mutex<Inner>,
+}
+
+/// Secrets are keyed by access key and environment.
+type SecretKey = (String, String);
+
+#[derive(Default)]
+struct Inner {
+    secrets: HashMap<SecretKey, EncryptedData>,
+    /// Secret histories, oldest first.
+    versions: HashMap<SecretKey, Vec<SecretVersion>>,
+    /// Base environments keyed by access key.
+    bases: HashMap<String, String>,
+    /// Pending shares keyed by project id.
+    shares: HashMap<String, ShareData>,
+}
+
+fn key(access_key: &str, environment: &str) -> SecretKey {
+    (access_key.to_string(), environment.to_string())
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, Inner>, StorageError> {
+        self.inner
+            .lock()
+            .map_err(|_| StorageError::Backend("memory storage poisoned".to_string()))
+    }
+}
+
+#[async_trait]
+impl Storage for MemoryStorage {
+    async fn insert_secret(&self, data: EncryptedData) -> Result<(), StorageError> {
+        let mut inner = self.lock()?;
+        let key = key(&data.access_key, &data.environment);
+        if inner.secrets.contains_key(&key)
+            || inner
+                .secrets
+                .values()
+                .any(|s| s.project_id == data.project_id && s.access_key != data.access_key)
+        {
+            return Err(StorageError::AlreadyExists);
+        }
+        inner.versions.insert(
+            key.clone(),
+            vec![SecretVersion::new(
+                &data.access_key,
+                &data.environment,
+                1,
+                &data.encrypted_content,
+            )],
+        );
+        inner
+            .secrets
+            .insert(key, EncryptedData { version: 1, ..data });
+        Ok(())
+    }
+
+    async fn find_secret(
+        &self,
+        access_key: &str,
+        environment: &str,
+    ) -> Result<Option<EncryptedData>, StorageError> {
+        Ok(self
+            .lock()?
+            .secrets
+            .get(&key(access_key, environment))
+            .cloned())
+    }
+
+    async fn list_environments(&self, access_key: &str) -> Result<Vec<String>, StorageError> {
+        let mut environments: Vec<String> = self
+            .lock()?
+            .secrets
+            .keys()
+            .filter(|(key, _)| key == access_key)
+            .map(|(_, environment)| environment.clone())
+            .collect();
+        environments.sort();
+        Ok(environments)
+    }
+
+    async fn find_base(&self, access_key: &str) -> Result<Option<String>, StorageError> {
+        Ok(self.lock()?.bases.get(access_key).cloned())
+    }
+
+    async fn set_base(
+        &self,
+        access_key: &str,
+        environment: Option<&str>,
+    ) -> Result<(), StorageError> {
+        let mut inner = self.lock()?;
+        match environment {
+            Some(environment) => inner
+                .bases
+                .insert(access_key.to_string(), environment.to_string()),
+            None => inner.bases.remove(access_key),
+        };
+        Ok(())
+    }
+
+    async fn update_secret(
+        &self,
+        access_key: &str,
+        environment: &str,
+        encrypted_content: &str,
+        expected_version: Option<u64>,
+    ) -> Result<Option<u64>, StorageError> {
+        let mut inner = self.lock()?;
+        let key = key(access_key, environment);
+        let version = match inner.secrets.get_mut(&key) {
+            Some(secret) => {
+                if expected_version.is_some_and(|expected| expected != secret.version) {
+                    return Err(StorageError::VersionMismatch(secret.version));
+                }
+                secret.version += 1;
+                secret.encrypted_content = encrypted_content.to_string();
+                secret.version
+            }
+            None => return Ok(None),
+        };
+        inner
+            .versions
+            .entry(key)
+            .or_default()
+            .push(SecretVersion::new(
+                access_key,
+                environment,
+                version,
+                encrypted_content,
+            ));
+        Ok(Some(version))
+    }
+
+    async fn delete_secret(
+        &self,
+        access_key: &str,
+        environment: Option<&str>,
+    ) -> Result<bool, StorageError> {
+        let mut inner = self.lock()?;
+        let matches = |(key, env): &SecretKey| {
+            key == access_key && environment.is_none_or(|environment| env == environment)
+        };
+        inner.versions.retain(|key, _| !matches(key));
+        let before = inner.secrets.len();
+        inner.secrets.retain(|key, _| !matches(key));
+        if inner
+            .bases
+            .get(access_key)
+            .is_some_and(|base| matches(&key(access_key, base)))
+        {
+            inner.bases.remove(access_key);
+        }
+        Ok(inner.secrets.len() != before)
+    }
+
+    async fn rotate_secrets(
+        &self,
+        access_key: &str,
+        secrets: Vec<RotatedSecret>,
+        new_access_key: Option<&str>,
+    ) -> Result<bool, StorageError> {
+        let mut inner = self.lock()?;
+        let current: Vec<SecretKey> = inner
+            .secrets
+            .keys()
+            .filter(|(key, _)| key == access_key)
+            .cloned()
+            .collect();
+        if current.is_empty() {
+            return Ok(false);
+        }
+        if current.len() != secrets.len()
+            || secrets
+                .iter()
+                .any(|s| !inner.secrets.contains_key(&key(access_key, &s.environment)))
+        {
+            return Err(StorageError::EnvironmentMismatch);
+        }
+        for secret in &secrets {
+            let version = inner.secrets[&key(access_key, &secret.environment)].version;
+            if version != secret.expected_version {
+                return Err(StorageError::VersionMismatch(version));
+            }
+        }
+
+        let new_access_key = new_access_key.unwrap_or(access_key);
+        for secret in secrets {
+            let old = inner
+                .secrets
+                .remove(&key(access_key, &secret.environment))
+                .unwrap();
+            inner.versions.remove(&key(access_key, &secret.environment));
+            let new_key = key(new_access_key, &secret.environment);
+            let version = old.version + 1;
+            inner.versions.insert(
+                new_key.clone(),
+                vec![SecretVersion::new(
+                    new_access_key,
+                    &secret.environment,
+                    version,
+                    &secret.encrypted_content,
+                )],
+            );
+            inner.secrets.insert(
+                new_key,
+                EncryptedData {
+                    access_key: new_access_key.to_string(),
+                    encrypted_content: secret.encrypted_content,
+                    version,
+                    ..old
+                },
+            );
+        }
+        if let Some(base) = inner.bases.remove(access_key) {
+            inner.bases.insert(new_access_key.to_string(), base);
+        }
+        Ok(true)
+    }
+
+    async fn list_versions(
+        &self,
+        access_key: &str,
+        environment: &str,
+    ) -> Result<Option<Vec<SecretVersion>>, StorageError> {
+        let inner = self.lock()?;
+        let key = key(access_key, environment);
+        if !inner.secrets.contains_key(&key) {
+            return Ok(None);
+        }
+        Ok(Some(inner.versions.get(&key).cloned().unwrap_or_default()))
+    }
+
+    async fn find_version(
+        &self,
+        access_key: &str,
+        environment: &str,
+        version: u64,
+    ) -> Result<Option<SecretVersion>, StorageError> {
+        Ok(self
+            .lock()?
+            .versions
+            .get(&key(access_key, environment))
+            .and_then(|versions| versions.iter().find(|v| v.version == version).cloned()))
+    }
+
+    async fn insert_share(&self, data: ShareData) -> Result<(), StorageError> {
+        let mut inner = self.lock()?;
+        if let Some(existing) = inner.shares.get(&data.project_id) {
+            if !existing.is_expired(unix_now()) {
+                return Err(StorageError::AlreadyExists);
+            }
+        }
+        inner.shares.insert(data.project_id.clone(), data);
+        Ok(())
+    }
+
+    async fn claim_share(
+        &self,
+        share_code: &str,
+        project_id: &str,
+    ) -> Result<Option<ShareData>, StorageError> {
+        let mut inner = self.lock()?;
+        let share = match inner.shares.get_mut(project_id) {
+            Some(share) if share.share_code == share_code && !share.is_expired(unix_now()) => share,
+            _ => return Ok(None),
+        };
+        if share.remaining_uses > 1 {
+            share.remaining_uses -= 1;
+            Ok(Some(share.clone()))
+        } else {
+            Ok(inner.shares.remove(project_id))
+        }
+    }
+
+    async fn purge_expired_shares(&self) -> Result<u64, StorageError> {
+        let mut inner = self.lock()?;
+        let now = unix_now();
+        let before = inner.shares.len();
+        inner.shares.retain(|_, share| !share.is_expired(now));
+        Ok((before - inner.shares.len()) as u64)
+    }
+}
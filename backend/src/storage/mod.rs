@@ -0,0 +1,267 @@
+pub mod memory;
+pub mod mongo;
+pub mod sqlite;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::env;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The environment used when a client doesn't name one, and for records from before
+/// environments existed.
+pub const DEFAULT_ENVIRONMENT: &str = "default";
+
+fn default_environment() -> String {
+    DEFAULT_ENVIRONMENT.to_string()
+}
+
+/// Whether `name` can be used as an environment name.
+pub fn is_valid_environment(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 64
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct EncryptedData {
+    pub project_id: String,
+    /// A project holds one secret per environment, all sharing the project's access key.
+    #[serde(default = "default_environment")]
+    pub environment: String,
+    pub encrypted_content: String,
+    pub access_key: String,
+    /// Number of the latest version; records stored before versioning existed read as 0.
+    #[serde(default)]
+    pub version: u64,
+}
+
+/// One entry of the append-only history kept for every secret.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SecretVersion {
+    pub access_key: String,
+    #[serde(default = "default_environment")]
+    pub environment: String,
+    pub version: u64,
+    /// Unix timestamp, in seconds.
+    pub created_at: i64,
+    /// Hex SHA-256 of `encrypted_content`.
+    pub content_hash: String,
+    pub encrypted_content: String,
+}
+
+impl SecretVersion {
+    pub fn new(access_key: &str, environment: &str, version: u64, encrypted_content: &str) -> Self {
+        SecretVersion {
+            access_key: access_key.to_string(),
+            environment: environment.to_string(),
+            version,
+            created_at: unix_now(),
+            content_hash: format!("{:x}", Sha256::digest(encrypted_content.as_bytes())),
+            encrypted_content: encrypted_content.to_string(),
+        }
+    }
+}
+
+/// The new content of one environment when a project's encryption key is rotated.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RotatedSecret {
+    pub environment: String,
+    pub encrypted_content: String,
+    /// The version the content was re-encrypted from.
+    pub expected_version: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ShareData {
+    pub project_id: String,
+    pub share_code: String,
+    pub encrypted_content: String,
+    /// Unix timestamp after which the share can no longer be claimed.
+    #[serde(default)]
+    pub expires_at: Option<i64>,
+    /// How many more times the share can be claimed; shares made before this existed allow one.
+    #[serde(default = "default_remaining_uses")]
+    pub remaining_uses: u32,
+}
+
+fn default_remaining_uses() -> u32 {
+    1
+}
+
+impl ShareData {
+    pub fn is_expired(&self, now: i64) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+}
+
+/// Current time as a Unix timestamp, in seconds.
+pub fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+#[derive(Debug)]
+pub enum StorageError {
+    AlreadyExists,
+    /// A conditional write expected another version; carries the current one.
+    VersionMismatch(u64),
+    /// A key rotation didn't cover exactly the environments of the project.
+    EnvironmentMismatch,
+    Backend(String),
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::AlreadyExists => write!(f, "record already exists"),
+            StorageError::VersionMismatch(current) => {
+                write!(f, "version mismatch, current version is {}", current)
+            }
+            StorageError::EnvironmentMismatch => {
+                write!(f, "environments don't match the project's")
+            }
+            StorageError::Backend(e) => write!(f, "storage backend error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+/// Persistence for pushed secrets and pending shares.
+///
+/// Handlers only talk to this trait, so the backend can be swapped at startup.
+#[async_trait]
+pub trait Storage: Send + Sync {
+    /// Stores a new secret as version 1. Fails with `AlreadyExists` if the project id belongs
+    /// to another access key, or the project already has the environment.
+    async fn insert_secret(&self, data: EncryptedData) -> Result<(), StorageError>;
+
+    async fn find_secret(
+        &self,
+        access_key: &str,
+        environment: &str,
+    ) -> Result<Option<EncryptedData>, StorageError>;
+
+    /// Lists the environments of a project by name; empty if the access key is unknown.
+    async fn list_environments(&self, access_key: &str) -> Result<Vec<String>, StorageError>;
+
+    /// The environment the other environments of a project are layered over, if it has one.
+    async fn find_base(&self, access_key: &str) -> Result<Option<String>, StorageError>;
+
+    /// Declares the base environment of a project, or removes the declaration with `None`.
+    async fn set_base(
+        &self,
+        access_key: &str,
+        environment: Option<&str>,
+    ) -> Result<(), StorageError>;
+
+    /// Replaces the content of a secret and appends it to the history.
+    ///
+    /// With `expected_version` set, the write only happens if it is still the latest
+    /// version, failing with `VersionMismatch` otherwise. Returns the new version number,
+    /// or `None` if the access key or environment is unknown.
+    async fn update_secret(
+        &self,
+        access_key: &str,
+        environment: &str,
+        encrypted_content: &str,
+        expected_version: Option<u64>,
+    ) -> Result<Option<u64>, StorageError>;
+
+    /// Removes one environment of a project with its history, or the whole project if
+    /// `environment` is `None`. Removing the project or its base environment also removes
+    /// the base declaration. Returns `false` if there was nothing to remove.
+    async fn delete_secret(
+        &self,
+        access_key: &str,
+        environment: Option<&str>,
+    ) -> Result<bool, StorageError>;
+
+    /// Replaces every environment of a project in one step with content re-encrypted under
+    /// a new key, and moves the project to `new_access_key` if given. Each environment gets
+    /// a new version; its older history can only be read with the old key and is dropped.
+    /// The base declaration moves along with the project.
+    ///
+    /// Nothing is written if an environment changed since it was re-encrypted
+    /// (`VersionMismatch`) or `secrets` doesn't cover exactly the project's environments
+    /// (`EnvironmentMismatch`). Returns `false` if the access key is unknown.
+    async fn rotate_secrets(
+        &self,
+        access_key: &str,
+        secrets: Vec<RotatedSecret>,
+        new_access_key: Option<&str>,
+    ) -> Result<bool, StorageError>;
+
+    /// Lists the history of a secret, oldest first, or `None` if the access key or
+    /// environment is unknown.
+    async fn list_versions(
+        &self,
+        access_key: &str,
+        environment: &str,
+    ) -> Result<Option<Vec<SecretVersion>>, StorageError>;
+
+    async fn find_version(
+        &self,
+        access_key: &str,
+        environment: &str,
+        version: u64,
+    ) -> Result<Option<SecretVersion>, StorageError>;
+
+    /// Stores a new share, failing with `AlreadyExists` if the project already has a live one.
+    /// An expired share for the same project is replaced.
+    async fn insert_share(&self, data: ShareData) -> Result<(), StorageError>;
+
+    /// Uses up one claim of a share, removing it once no uses are left.
+    /// Expired shares are never returned.
+    async fn claim_share(
+        &self,
+        share_code: &str,
+        project_id: &str,
+    ) -> Result<Option<ShareData>, StorageError>;
+
+    /// Removes every expired share, returning how many were deleted.
+    async fn purge_expired_shares(&self) -> Result<u64, StorageError>;
+}
+
+/// Builds the storage backend selected by the `STORAGE` environment variable.
+pub async fn from_env() -> Result<Arc<dyn Storage>, Box<dyn std::error::Error>> {
+    let kind = env::var("STORAGE").unwrap_or_else(|_| "mongo".to_string());
+    match kind.as_str() {
+        "mongo" => {
+            let database_url = env::var("DATABASE_URL").map_err(|_| "DATABASE_URL must be set")?;
+            let database_name =
+                env::var("DATABASE_NAME").map_err(|_| "DATABASE_NAME must be set")?;
+            Ok(Arc::new(
+                mongo::MongoStorage::connect(&database_url, &database_name).await?,
+            ))
+        }
+        "sqlite" => {
+            let path = env::var("SQLITE_PATH").unwrap_or_else(|_| "dotenvpull.db".to_string());
+            Ok(Arc::new(sqlite::SqliteStorage::open(&path)?))
+        }
+        "memory" => Ok(Arc::new(memory::MemoryStorage::new())),
+        other => Err(format!("Unknown STORAGE backend '{}'", other).into()),
+    }
+}
+
+/// Periodically removes expired shares, so unclaimed ones don't pile up.
+pub fn spawn_share_purger(storage: Arc<dyn Storage>, every: Duration) {
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(every);
+        loop {
+            interval.tick().await;
+            match storage.purge_expired_shares().await {
+                Ok(0) => {}
+                Ok(purged) => log::info!("Purged {} expired shares", purged),
+                Err(e) => log::warn!("Failed to purge expired shares: {}", e),
+            }
+        }
+    });
+}
@@ -0,0 +1,391 @@
+use super::{
+    unix_now, EncryptedData, RotatedSecret, SecretVersion, ShareData, Storage, StorageError,
+    DEFAULT_ENVIRONMENT,
+};
+use async_trait::async_trait;
+use bson::{doc, Bson, Document};
+use futures::stream::TryStreamExt;
+use mongodb::options::{
+    ClientOptions, FindOneAndUpdateOptions, FindOptions, ReplaceOptions, ReturnDocument,
+};
+use mongodb::{Client, Collection, Database};
+use serde::{Deserialize, Serialize};
+
+pub struct MongoStorage {
+    client: Client,
+    db: Database,
+}
+
+impl MongoStorage {
+    pub async fn connect(database_url: &str, database_name: &str) -> mongodb::error::Result<Self> {
+        let client_options = ClientOptions::parse(database_url).await?;
+        let client = Client::with_options(client_options)?;
+        Ok(MongoStorage {
+            db: client.database(database_name),
+            client,
+        })
+    }
+
+    fn secrets(&self) -> Collection<EncryptedData> {
+        self.db.collection::<EncryptedData>("encrypted_data")
+    }
+
+    fn versions(&self) -> Collection<SecretVersion> {
+        self.db.collection::<SecretVersion>("secret_versions")
+    }
+
+    fn shares(&self) -> Collection<ShareData> {
+        self.db.collection::<ShareData>("share_data")
+    }
+
+    fn bases(&self) -> Collection<ProjectBase> {
+        self.db.collection::<ProjectBase>("project_bases")
+    }
+}
+
+/// The environment a project's other environments are layered over.
+#[derive(Serialize, Deserialize)]
+struct ProjectBase {
+    access_key: String,
+    environment: String,
+}
+
+/// Matches the secret of one environment. Documents from before environments existed
+/// have no `environment` field and belong to the default one.
+fn secret_filter(access_key: &str, environment: &str) -> Document {
+    if environment == DEFAULT_ENVIRONMENT {
+        doc! { "access_key": access_key, "environment": { "$in": [environment, Bson::Null] } }
+    } else {
+        doc! { "access_key": access_key, "environment": environment }
+    }
+}
+
+impl From<mongodb::error::Error> for StorageError {
+    fn from(e: mongodb::error::Error) -> Self {
+        StorageError::Backend(e.to_string())
+    }
+}
+
+#[async_trait]
+impl Storage for MongoStorage {
+    async fn insert_secret(&self, data: EncryptedData) -> Result<(), StorageError> {
+        let collection = self.secrets();
+        let taken = doc! {
+            "$or": [
+                { "project_id": &data.project_id, "access_key": { "$ne": &data.access_key } },
+                secret_filter(&data.access_key, &data.environment),
+            ]
+        };
+        if collection.find_one(taken, None).await?.is_some() {
+            return Err(StorageError::AlreadyExists);
+        }
+        let version = SecretVersion::new(
+            &data.access_key,
+            &data.environment,
+            1,
+            &data.encrypted_content,
+        );
+        collection
+            .insert_one(EncryptedData { version: 1, ..data }, None)
+            .await?;
+        self.versions().insert_one(version, None).await?;
+        Ok(())
+    }
+
+    async fn find_secret(
+        &self,
+        access_key: &str,
+        environment: &str,
+    ) -> Result<Option<EncryptedData>, StorageError> {
+        Ok(self
+            .secrets()
+            .find_one(secret_filter(access_key, environment), None)
+            .await?)
+    }
+
+    async fn list_environments(&self, access_key: &str) -> Result<Vec<String>, StorageError> {
+        let secrets: Vec<EncryptedData> = self
+            .secrets()
+            .find(doc! { "access_key": access_key }, None)
+            .await?
+            .try_collect()
+            .await?;
+        let mut environments: Vec<String> = secrets.into_iter().map(|s| s.environment).collect();
+        environments.sort();
+        Ok(environments)
+    }
+
+    async fn find_base(&self, access_key: &str) -> Result<Option<String>, StorageError> {
+        Ok(self
+            .bases()
+            .find_one(doc! { "access_key": access_key }, None)
+            .await?
+            .map(|base| base.environment))
+    }
+
+    async fn set_base(
+        &self,
+        access_key: &str,
+        environment: Option<&str>,
+    ) -> Result<(), StorageError> {
+        let filter = doc! { "access_key": access_key };
+        match environment {
+            Some(environment) => {
+                let options = ReplaceOptions::builder().upsert(true).build();
+                let base = ProjectBase {
+                    access_key: access_key.to_string(),
+                    environment: environment.to_string(),
+                };
+                self.bases().replace_one(filter, base, options).await?;
+            }
+            None => {
+                self.bases().delete_one(filter, None).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn update_secret(
+        &self,
+        access_key: &str,
+        environment: &str,
+        encrypted_content: &str,
+        expected_version: Option<u64>,
+    ) -> Result<Option<u64>, StorageError> {
+        let mut filter = secret_filter(access_key, environment);
+        match expected_version {
+            // Records from before versioning have no version field at all.
+            Some(0) => filter.insert("version", doc! { "$in": [0_i64, Bson::Null] }),
+            Some(version) => filter.insert("version", version as i64),
+            None => None,
+        };
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+        let updated = self
+            .secrets()
+            .find_one_and_update(
+                filter,
+                doc! {
+                    "$set": { "encrypted_content": encrypted_content, "environment": environment },
+                    "$inc": { "version": 1_i64 },
+                },
+                options,
+            )
+            .await?;
+        match updated {
+            Some(data) => {
+                self.versions()
+                    .insert_one(
+                        SecretVersion::new(
+                            access_key,
+                            environment,
+                            data.version,
+                            encrypted_content,
+                        ),
+                        None,
+                    )
+                    .await?;
+                Ok(Some(data.version))
+            }
+            None => match (
+                expected_version,
+                self.find_secret(access_key, environment).await?,
+            ) {
+                (Some(_), Some(current)) => Err(StorageError::VersionMismatch(current.version)),
+                _ => Ok(None),
+            },
+        }
+    }
+
+    async fn delete_secret(
+        &self,
+        access_key: &str,
+        environment: Option<&str>,
+    ) -> Result<bool, StorageError> {
+        let filter = match environment {
+            Some(environment) => secret_filter(access_key, environment),
+            None => doc! { "access_key": access_key },
+        };
+        let deleted = self
+            .secrets()
+            .delete_many(filter.clone(), None)
+            .await?
+            .deleted_count
+            > 0;
+        if deleted {
+            self.versions().delete_many(filter, None).await?;
+            let mut base = doc! { "access_key": access_key };
+            if let Some(environment) = environment {
+                base.insert("environment", environment);
+            }
+            self.bases().delete_one(base, None).await?;
+        }
+        Ok(deleted)
+    }
+
+    /// Runs in a transaction, which MongoDB only supports on a replica set or sharded cluster.
+    async fn rotate_secrets(
+        &self,
+        access_key: &str,
+        secrets: Vec<RotatedSecret>,
+        new_access_key: Option<&str>,
+    ) -> Result<bool, StorageError> {
+        let new_access_key = new_access_key.unwrap_or(access_key);
+        let mut session = self.client.start_session(None).await?;
+        session.start_transaction(None).await?;
+
+        let current: Vec<EncryptedData> = self
+            .secrets()
+            .find_with_session(doc! { "access_key": access_key }, None, &mut session)
+            .await?
+            .stream(&mut session)
+            .try_collect()
+            .await?;
+        if current.is_empty() {
+            return Ok(false);
+        }
+        if current.len() != secrets.len() {
+            return Err(StorageError::EnvironmentMismatch);
+        }
+        for secret in &secrets {
+            match current.iter().find(|s| s.environment == secret.environment) {
+                Some(s) if s.version == secret.expected_version => {}
+                Some(s) => return Err(StorageError::VersionMismatch(s.version)),
+                None => return Err(StorageError::EnvironmentMismatch),
+            }
+        }
+
+        self.versions()
+            .delete_many_with_session(doc! { "access_key": access_key }, None, &mut session)
+            .await?;
+        let mut versions = Vec::with_capacity(secrets.len());
+        for secret in &secrets {
+            let version = secret.expected_version + 1;
+            self.secrets()
+                .update_one_with_session(
+                    secret_filter(access_key, &secret.environment),
+                    doc! {
+                        "$set": {
+                            "encrypted_content": &secret.encrypted_content,
+                            "environment": &secret.environment,
+                            "access_key": new_access_key,
+                            "version": version as i64,
+                        },
+                    },
+                    None,
+                    &mut session,
+                )
+                .await?;
+            versions.push(SecretVersion::new(
+                new_access_key,
+                &secret.environment,
+                version,
+                &secret.encrypted_content,
+            ));
+        }
+        self.versions()
+            .insert_many_with_session(versions, None, &mut session)
+            .await?;
+        self.bases()
+            .update_one_with_session(
+                doc! { "access_key": access_key },
+                doc! { "$set": { "access_key": new_access_key } },
+                None,
+                &mut session,
+            )
+            .await?;
+        session.commit_transaction().await?;
+        Ok(true)
+    }
+
+    async fn list_versions(
+        &self,
+        access_key: &str,
+        environment: &str,
+    ) -> Result<Option<Vec<SecretVersion>>, StorageError> {
+        if self.find_secret(access_key, environment).await?.is_none() {
+            return Ok(None);
+        }
+        let options = FindOptions::builder().sort(doc! { "version": 1 }).build();
+        let versions = self
+            .versions()
+            .find(secret_filter(access_key, environment), options)
+            .await?
+            .try_collect()
+            .await?;
+        Ok(Some(versions))
+    }
+
+    async fn find_version(
+        &self,
+        access_key: &str,
+        environment: &str,
+        version: u64,
+    ) -> Result<Option<SecretVersion>, StorageError> {
+        let mut filter = secret_filter(access_key, environment);
+        filter.insert("version", version as i64);
+        Ok(self.versions().find_one(filter, None).await?)
+    }
+
+    async fn insert_share(&self, data: ShareData) -> Result<(), StorageError> {
+        let collection = self.shares();
+        collection
+            .delete_many(
+                doc! { "project_id": &data.project_id, "expires_at": { "$lte": unix_now() } },
+                None,
+            )
+            .await?;
+        if collection
+            .find_one(doc! { "project_id": &data.project_id }, None)
+            .await?
+            .is_some()
+        {
+            return Err(StorageError::AlreadyExists);
+        }
+        collection.insert_one(data, None).await?;
+        Ok(())
+    }
+
+    async fn claim_share(
+        &self,
+        share_code: &str,
+        project_id: &str,
+    ) -> Result<Option<ShareData>, StorageError> {
+        let live = doc! {
+            "share_code": share_code,
+            "project_id": project_id,
+            "$or": [
+                { "expires_at": Bson::Null },
+                { "expires_at": { "$gt": unix_now() } },
+            ],
+        };
+
+        // Take one use if more than one is left, otherwise claim the last use by deleting.
+        let mut with_uses_left = live.clone();
+        with_uses_left.insert("remaining_uses", doc! { "$gt": 1_i64 });
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+        if let Some(share) = self
+            .shares()
+            .find_one_and_update(
+                with_uses_left,
+                doc! { "$inc": { "remaining_uses": -1_i64 } },
+                options,
+            )
+            .await?
+        {
+            return Ok(Some(share));
+        }
+        Ok(self.shares().find_one_and_delete(live, None).await?)
+    }
+
+    async fn purge_expired_shares(&self) -> Result<u64, StorageError> {
+        Ok(self
+            .shares()
+            .delete_many(doc! { "expires_at": { "$lte": unix_now() } }, None)
+            .await?
+            .deleted_count)
+    }
+}
@@ -0,0 +1,618 @@
+use super::{
+    unix_now, EncryptedData, RotatedSecret, SecretVersion, ShareData, Storage, StorageError,
+};
+use async_trait::async_trait;
+use rusqlite::{params, Connection, ErrorCode, OptionalExtension};
+use std::sync::{Arc, Mutex};
+
+/// Schema migrations, applied in order. `PRAGMA user_version` records how many have run.
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE encrypted_data (
+        project_id TEXT NOT NULL UNIQUE,
+        encrypted_content TEXT NOT NULL,
+        access_key TEXT NOT NULL UNIQUE
+    );
+    CREATE TABLE share_data (
+        project_id TEXT NOT NULL UNIQUE,
+        share_code TEXT NOT NULL,
+        encrypted_content TEXT NOT NULL
+    );",
+    "ALTER TABLE encrypted_data ADD COLUMN version INTEGER NOT NULL DEFAULT 0;
+    CREATE TABLE secret_versions (
+        access_key TEXT NOT NULL,
+        version INTEGER NOT NULL,
+        created_at INTEGER NOT NULL,
+        content_hash TEXT NOT NULL,
+        encrypted_content TEXT NOT NULL,
+        PRIMARY KEY (access_key, version)
+    );",
+    "ALTER TABLE share_data ADD COLUMN expires_at INTEGER;
+    ALTER TABLE share_data ADD COLUMN remaining_uses INTEGER NOT NULL DEFAULT 1;",
+    // Environments: secrets are keyed by access key and environment instead of access key.
+    "CREATE TABLE encrypted_data_new (
+        project_id TEXT NOT NULL,
+        environment TEXT NOT NULL,
+        encrypted_content TEXT NOT NULL,
+        access_key TEXT NOT NULL,
+        version INTEGER NOT NULL DEFAULT 0,
+        PRIMARY KEY (access_key, environment)
+    );
+    INSERT INTO encrypted_data_new (project_id, environment, encrypted_content, access_key, version)
+        SELECT project_id, 'default', encrypted_content, access_key, version FROM encrypted_data;
+    DROP TABLE encrypted_data;
+    ALTER TABLE encrypted_data_new RENAME TO encrypted_data;
+    CREATE INDEX encrypted_data_project_id ON encrypted_data (project_id);
+    CREATE TABLE secret_versions_new (
+        access_key TEXT NOT NULL,
+        environment TEXT NOT NULL,
+        version INTEGER NOT NULL,
+        created_at INTEGER NOT NULL,
+        content_hash TEXT NOT NULL,
+        encrypted_content TEXT NOT NULL,
+        PRIMARY KEY (access_key, environment, version)
+    );
+    INSERT INTO secret_versions_new
+        SELECT access_key, 'default', version, created_at, content_hash, encrypted_content
+        FROM secret_versions;
+    DROP TABLE secret_versions;
+    ALTER TABLE secret_versions_new RENAME TO secret_versions;",
+    "CREATE TABLE project_bases (
+        access_key TEXT NOT NULL PRIMARY KEY,
+        environment TEXT NOT NULL
+    );",
+];
+
+pub struct SqliteStorage {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl SqliteStorage {
+    pub fn open(path: &str) -> rusqlite::Result<Self> {
+        let mut conn = Connection::open(path)?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        migrate(&mut conn)?;
+        Ok(SqliteStorage {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    /// Runs a query on the blocking thread pool so the connection never stalls the executor.
+    async fn with_conn<T, F>(&self, f: F) -> Result<T, StorageError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> Result<T, StorageError> + Send + 'static,
+    {
+        let conn = self.conn.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut conn = conn
+                .lock()
+                .map_err(|_| StorageError::Backend("SQLite connection poisoned".to_string()))?;
+            f(&mut conn)
+        })
+        .await
+        .map_err(|e| StorageError::Backend(e.to_string()))?
+    }
+}
+
+fn insert_version(conn: &Connection, version: &SecretVersion) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO secret_versions
+         (access_key, environment, version, created_at, content_hash, encrypted_content)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            version.access_key,
+            version.environment,
+            version.version,
+            version.created_at,
+            version.content_hash,
+            version.encrypted_content
+        ],
+    )?;
+    Ok(())
+}
+
+fn version_from_row(row: &rusqlite::Row) -> rusqlite::Result<SecretVersion> {
+    Ok(SecretVersion {
+        access_key: row.get(0)?,
+        environment: row.get(1)?,
+        version: row.get(2)?,
+        created_at: row.get(3)?,
+        content_hash: row.get(4)?,
+        encrypted_content: row.get(5)?,
+    })
+}
+
+fn migrate(conn: &mut Connection) -> rusqlite::Result<()> {
+    let applied: usize = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(applied) {
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration)?;
+        tx.pragma_update(None, "user_version", index + 1)?;
+        tx.commit()?;
+    }
+    Ok(())
+}
+
+impl From<rusqlite::Error> for StorageError {
+    fn from(e: rusqlite::Error) -> Self {
+        match e.sqlite_error_code() {
+            Some(ErrorCode::ConstraintViolation) => StorageError::AlreadyExists,
+            _ => StorageError::Backend(e.to_string()),
+        }
+    }
+}
+
+#[async_trait]
+impl Storage for SqliteStorage {
+    async fn insert_secret(&self, data: EncryptedData) -> Result<(), StorageError> {
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            let taken = tx
+                .query_row(
+                    "SELECT 1 FROM encrypted_data WHERE project_id = ?1 AND access_key != ?2",
+                    params![data.project_id, data.access_key],
+                    |_| Ok(()),
+                )
+                .optional()?
+                .is_some();
+            if taken {
+                return Err(StorageError::AlreadyExists);
+            }
+            tx.execute(
+                "INSERT INTO encrypted_data
+                 (project_id, environment, encrypted_content, access_key, version)
+                 VALUES (?1, ?2, ?3, ?4, 1)",
+                params![
+                    data.project_id,
+                    data.environment,
+                    data.encrypted_content,
+                    data.access_key
+                ],
+            )?;
+            insert_version(
+                &tx,
+                &SecretVersion::new(
+                    &data.access_key,
+                    &data.environment,
+                    1,
+                    &data.encrypted_content,
+                ),
+            )?;
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn find_secret(
+        &self,
+        access_key: &str,
+        environment: &str,
+    ) -> Result<Option<EncryptedData>, StorageError> {
+        let access_key = access_key.to_string();
+        let environment = environment.to_string();
+        self.with_conn(move |conn| {
+            Ok(conn
+                .query_row(
+                    "SELECT project_id, environment, encrypted_content, access_key, version
+                     FROM encrypted_data WHERE access_key = ?1 AND environment = ?2",
+                    params![access_key, environment],
+                    |row| {
+                        Ok(EncryptedData {
+                            project_id: row.get(0)?,
+                            environment: row.get(1)?,
+                            encrypted_content: row.get(2)?,
+                            access_key: row.get(3)?,
+                            version: row.get(4)?,
+                        })
+                    },
+                )
+                .optional()?)
+        })
+        .await
+    }
+
+    async fn list_environments(&self, access_key: &str) -> Result<Vec<String>, StorageError> {
+        let access_key = access_key.to_string();
+        self.with_conn(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT environment FROM encrypted_data WHERE access_key = ?1 ORDER BY environment",
+            )?;
+            let environments = stmt
+                .query_map(params![access_key], |row| row.get(0))?
+                .collect::<rusqlite::Result<Vec<String>>>()?;
+            Ok(environments)
+        })
+        .await
+    }
+
+    async fn find_base(&self, access_key: &str) -> Result<Option<String>, StorageError> {
+        let access_key = access_key.to_string();
+        self.with_conn(move |conn| {
+            Ok(conn
+                .query_row(
+                    "SELECT environment FROM project_bases WHERE access_key = ?1",
+                    params![access_key],
+                    |row| row.get(0),
+                )
+                .optional()?)
+        })
+        .await
+    }
+
+    async fn set_base(
+        &self,
+        access_key: &str,
+        environment: Option<&str>,
+    ) -> Result<(), StorageError> {
+        let access_key = access_key.to_string();
+        let environment = environment.map(str::to_string);
+        self.with_conn(move |conn| {
+            match environment {
+                Some(environment) => conn.execute(
+                    "INSERT OR REPLACE INTO project_bases (access_key, environment) VALUES (?1, ?2)",
+                    params![access_key, environment],
+                )?,
+                None => conn.execute(
+                    "DELETE FROM project_bases WHERE access_key = ?1",
+                    params![access_key],
+                )?,
+            };
+            Ok(())
+        })
+        .await
+    }
+
+    async fn update_secret(
+        &self,
+        access_key: &str,
+        environment: &str,
+        encrypted_content: &str,
+        expected_version: Option<u64>,
+    ) -> Result<Option<u64>, StorageError> {
+        let access_key = access_key.to_string();
+        let environment = environment.to_string();
+        let encrypted_content = encrypted_content.to_string();
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            let current: Option<u64> = tx
+                .query_row(
+                    "SELECT version FROM encrypted_data WHERE access_key = ?1 AND environment = ?2",
+                    params![access_key, environment],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            let current = match current {
+                Some(current) => current,
+                None => return Ok(None),
+            };
+            if expected_version.is_some_and(|expected| expected != current) {
+                return Err(StorageError::VersionMismatch(current));
+            }
+            let version = current + 1;
+            tx.execute(
+                "UPDATE encrypted_data SET encrypted_content = ?1, version = ?2
+                 WHERE access_key = ?3 AND environment = ?4",
+                params![encrypted_content, version, access_key, environment],
+            )?;
+            insert_version(
+                &tx,
+                &SecretVersion::new(&access_key, &environment, version, &encrypted_content),
+            )?;
+            tx.commit()?;
+            Ok(Some(version))
+        })
+        .await
+    }
+
+    async fn delete_secret(
+        &self,
+        access_key: &str,
+        environment: Option<&str>,
+    ) -> Result<bool, StorageError> {
+        let access_key = access_key.to_string();
+        let environment = environment.map(str::to_string);
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            // A NULL environment matches every environment of the project.
+            let deleted = tx.execute(
+                "DELETE FROM encrypted_data
+                 WHERE access_key = ?1 AND (?2 IS NULL OR environment = ?2)",
+                params![access_key, environment],
+            )?;
+            tx.execute(
+                "DELETE FROM secret_versions
+                 WHERE access_key = ?1 AND (?2 IS NULL OR environment = ?2)",
+                params![access_key, environment],
+            )?;
+            tx.execute(
+                "DELETE FROM project_bases
+                 WHERE access_key = ?1 AND (?2 IS NULL OR environment = ?2)",
+                params![access_key, environment],
+            )?;
+            tx.commit()?;
+            Ok(deleted > 0)
+        })
+        .await
+    }
+
+    async fn rotate_secrets(
+        &self,
+        access_key: &str,
+        secrets: Vec<RotatedSecret>,
+        new_access_key: Option<&str>,
+    ) -> Result<bool, StorageError> {
+        let access_key = access_key.to_string();
+        let new_access_key = new_access_key.unwrap_or(&access_key).to_string();
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            let current = {
+                let mut stmt = tx.prepare(
+                    "SELECT environment, version FROM encrypted_data WHERE access_key = ?1",
+                )?;
+                let rows = stmt
+                    .query_map(params![access_key], |row| {
+                        Ok((row.get::<_, String>(0)?, row.get::<_, u64>(1)?))
+                    })?
+                    .collect::<rusqlite::Result<Vec<_>>>()?;
+                rows
+            };
+            if current.is_empty() {
+                return Ok(false);
+            }
+            if current.len() != secrets.len() {
+                return Err(StorageError::EnvironmentMismatch);
+            }
+            for secret in &secrets {
+                match current.iter().find(|(env, _)| *env == secret.environment) {
+                    Some((_, version)) if *version == secret.expected_version => {}
+                    Some((_, version)) => return Err(StorageError::VersionMismatch(*version)),
+                    None => return Err(StorageError::EnvironmentMismatch),
+                }
+            }
+
+            tx.execute(
+                "DELETE FROM secret_versions WHERE access_key = ?1",
+                params![access_key],
+            )?;
+            for secret in &secrets {
+                let version = secret.expected_version + 1;
+                tx.execute(
+                    "UPDATE encrypted_data SET encrypted_content = ?1, version = ?2, access_key = ?3
+                     WHERE access_key = ?4 AND environment = ?5",
+                    params![
+                        secret.encrypted_content,
+                        version,
+                        new_access_key,
+                        access_key,
+                        secret.environment
+                    ],
+                )?;
+                insert_version(
+                    &tx,
+                    &SecretVersion::new(
+                        &new_access_key,
+                        &secret.environment,
+                        version,
+                        &secret.encrypted_content,
+                    ),
+                )?;
+            }
+            tx.execute(
+                "UPDATE project_bases SET access_key = ?1 WHERE access_key = ?2",
+                params![new_access_key, access_key],
+            )?;
+            tx.commit()?;
+            Ok(true)
+        })
+        .await
+    }
+
+    async fn list_versions(
+        &self,
+        access_key: &str,
+        environment: &str,
+    ) -> Result<Option<Vec<SecretVersion>>, StorageError> {
+        let access_key = access_key.to_string();
+        let environment = environment.to_string();
+        self.with_conn(move |conn| {
+            let exists = conn
+                .query_row(
+                    "SELECT 1 FROM encrypted_data WHERE access_key = ?1 AND environment = ?2",
+                    params![access_key, environment],
+                    |_| Ok(()),
+                )
+                .optional()?
+                .is_some();
+            if !exists {
+                return Ok(None);
+            }
+            let mut stmt = conn.prepare(
+                "SELECT access_key, environment, version, created_at, content_hash, encrypted_content
+                 FROM secret_versions WHERE access_key = ?1 AND environment = ?2 ORDER BY version",
+            )?;
+            let versions = stmt
+                .query_map(params![access_key, environment], version_from_row)?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok(Some(versions))
+        })
+        .await
+    }
+
+    async fn find_version(
+        &self,
+        access_key: &str,
+        environment: &str,
+        version: u64,
+    ) -> Result<Option<SecretVersion>, StorageError> {
+        let access_key = access_key.to_string();
+        let environment = environment.to_string();
+        self.with_conn(move |conn| {
+            Ok(conn
+                .query_row(
+                    "SELECT access_key, environment, version, created_at, content_hash, encrypted_content
+                     FROM secret_versions
+                     WHERE access_key = ?1 AND environment = ?2 AND version = ?3",
+                    params![access_key, environment, version],
+                    version_from_row,
+                )
+                .optional()?)
+        })
+        .await
+    }
+
+    async fn insert_share(&self, data: ShareData) -> Result<(), StorageError> {
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            tx.execute(
+                "DELETE FROM share_data WHERE project_id = ?1 AND expires_at <= ?2",
+                params![data.project_id, unix_now()],
+            )?;
+            tx.execute(
+                "INSERT INTO share_data
+                 (project_id, share_code, encrypted_content, expires_at, remaining_uses)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    data.project_id,
+                    data.share_code,
+                    data.encrypted_content,
+                    data.expires_at,
+                    data.remaining_uses
+                ],
+            )?;
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn claim_share(
+        &self,
+        share_code: &str,
+        project_id: &str,
+    ) -> Result<Option<ShareData>, StorageError> {
+        let share_code = share_code.to_string();
+        let project_id = project_id.to_string();
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            let share = tx
+                .query_row(
+                    "SELECT project_id, share_code, encrypted_content, expires_at, remaining_uses
+                     FROM share_data WHERE share_code = ?1 AND project_id = ?2",
+                    params![share_code, project_id],
+                    |row| {
+                        Ok(ShareData {
+                            project_id: row.get(0)?,
+                            share_code: row.get(1)?,
+                            encrypted_content: row.get(2)?,
+                            expires_at: row.get(3)?,
+                            remaining_uses: row.get(4)?,
+                        })
+                    },
+                )
+                .optional()?;
+            let mut share = match share {
+                Some(share) if !share.is_expired(unix_now()) => share,
+                _ => return Ok(None),
+            };
+            if share.remaining_uses > 1 {
+                share.remaining_uses -= 1;
+                tx.execute(
+                    "UPDATE share_data SET remaining_uses = ?1 WHERE project_id = ?2",
+                    params![share.remaining_uses, project_id],
+                )?;
+            } else {
+                tx.execute(
+                    "DELETE FROM share_data WHERE project_id = ?1",
+                    params![project_id],
+                )?;
+            }
+            tx.commit()?;
+            Ok(Some(share))
+        })
+        .await
+    }
+
+    async fn purge_expired_shares(&self) -> Result<u64, StorageError> {
+        self.with_conn(move |conn| {
+            let purged = conn.execute(
+                "DELETE FROM share_data WHERE expires_at <= ?1",
+                params![unix_now()],
+            )?;
+            Ok(purged as u64)
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Brings a database to `version` the way an older server did.
+    fn migrate_to(conn: &Connection, from: usize, version: usize) {
+        for migration in &MIGRATIONS[from..version] {
+            conn.execute_batch(migration).unwrap();
+        }
+        conn.pragma_update(None, "user_version", version).unwrap();
+    }
+
+    #[tokio::test]
+    async fn databases_from_the_first_schema_are_migrated() {
+        let path = std::env::temp_dir().join(format!("dotenvpull-{}.db", uuid::Uuid::new_v4()));
+        let path = path.to_str().unwrap().to_string();
+
+        // A secret stored by the first release, and a version written once histories existed.
+        let conn = Connection::open(&path).unwrap();
+        migrate_to(&conn, 0, 1);
+        conn.execute(
+            "INSERT INTO encrypted_data (project_id, encrypted_content, access_key)
+             VALUES ('app', 'v1', 'ak')",
+            [],
+        )
+        .unwrap();
+        migrate_to(&conn, 1, 3);
+        conn.execute_batch(
+            "UPDATE encrypted_data SET encrypted_content = 'v2', version = 2;
+             INSERT INTO secret_versions VALUES ('ak', 2, 1700000000, 'hash', 'v2');",
+        )
+        .unwrap();
+        drop(conn);
+
+        let storage = SqliteStorage::open(&path).unwrap();
+        let secret = storage.find_secret("ak", "default").await.unwrap().unwrap();
+        assert_eq!(secret.project_id, "app");
+        assert_eq!(secret.encrypted_content, "v2");
+        assert_eq!(secret.version, 2);
+        let versions = storage
+            .list_versions("ak", "default")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(versions.len(), 1);
+        assert_eq!(versions[0].version, 2);
+        assert_eq!(versions[0].created_at, 1700000000);
+        assert_eq!(versions[0].encrypted_content, "v2");
+
+        // The rebuilt tables take more environments of the same project.
+        storage
+            .insert_secret(EncryptedData {
+                project_id: "app".to_string(),
+                environment: "staging".to_string(),
+                encrypted_content: "s1".to_string(),
+                access_key: "ak".to_string(),
+                version: 0,
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            storage.list_environments("ak").await.unwrap(),
+            ["default", "staging"]
+        );
+
+        drop(storage);
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", path, suffix));
+        }
+    }
+}
@@ -0,0 +1,73 @@
+use crate::api::http_client;
+use crate::config::load_config;
+use serde_json::{json, Value};
+
+/// The environment a project's other environments are layered over, as declared on its server.
+pub(crate) async fn fetch_base(
+    api_url: &str,
+    project_name: &str,
+) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let config = load_config()?;
+    let project = config.project(project_name)?;
+
+    let response = http_client(api_url)?
+        .get(format!("{}/environments", api_url))
+        .header("X-API-Key", &project.access_key)
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        return Err(format!(
+            "Could not look up the base environment of '{}': {}",
+            project_name,
+            response.text().await?
+        )
+        .into());
+    }
+
+    let json: Value = response.json().await?;
+    Ok(json["base"].as_str().map(String::from))
+}
+
+/// Prints the environment a project's other environments are layered over, and returns it.
+pub async fn get_base(
+    api_url: &str,
+    project_name: &str,
+) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let base = fetch_base(api_url, project_name).await?;
+    match &base {
+        Some(base) => println!("'{}' is layered over '{}'", project_name, base),
+        None => println!("'{}' has no base environment", project_name),
+    }
+    Ok(base)
+}
+
+/// Declares the environment a project's other environments are layered over, or removes it
+/// with `None`. It is kept on the server, so everyone with access to the project shares it.
+pub async fn set_base(
+    api_url: &str,
+    project_name: &str,
+    environment: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let config = load_config()?;
+    let project = config.project(project_name)?;
+
+    let response = http_client(api_url)?
+        .put(format!("{}/base", api_url))
+        .header("X-API-Key", &project.access_key)
+        .json(&json!({ "base": environment }))
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        println!("Error: {}", response.text().await?);
+        return Ok(());
+    }
+
+    match environment {
+        Some(environment) => println!(
+            "Environments of '{}' are now layered over '{}'",
+            project_name, environment
+        ),
+        None => println!("'{}' no longer has a base environment", project_name),
+    }
+    Ok(())
+}
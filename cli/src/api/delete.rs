@@ -1,35 +1,38 @@
-use crate::config::get_or_create_config;
-use reqwest::Client;
-use std::fs;
+use crate::api::http_client;
+use crate::config::{load_config, save_config, set_project_revision};
 
-pub async fn delete(api_url: &str, project_name: &str) -> Result<(), Box<dyn std::error::Error>> {
-    let mut config = get_or_create_config()?;
-    let project_config = config
-        .get(project_name)
-        .ok_or("Project not found in config")?;
-    let access_key = project_config
-        .get("access_key")
-        .ok_or("No access key found")?
-        .as_str()
-        .unwrap();
+/// Deletes one environment of a project, or the whole project if `environment` is `None`.
+pub async fn delete(
+    api_url: &str,
+    project_name: &str,
+    environment: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut config = load_config()?;
+    let project = config.project(project_name)?;
+    let access_key = project.access_key.as_str();
 
-    let client = Client::new();
-    let response = client
-        .delete(&format!("{}/delete", api_url))
-        .header("X-API-Key", access_key)
-        .send()
-        .await?;
+    let client = http_client(api_url)?;
+    let mut request = client
+        .delete(format!("{}/delete", api_url))
+        .header("X-API-Key", access_key);
+    if let Some(environment) = environment {
+        request = request.header("X-Environment", environment);
+    }
+    let response = request.send().await?;
 
-    if response.status().is_success() {
+    if !response.status().is_success() {
+        println!("Error: {}", response.text().await?);
+    } else if let Some(environment) = environment {
+        set_project_revision(project_name, environment, None)?;
+        println!(
+            "Environment '{}' of '{}' deleted successfully",
+            environment, project_name
+        );
+    } else {
         println!("File deleted successfully");
-        config.as_object_mut().unwrap().remove(project_name);
-        fs::write(
-            "dotenvpull_config.json",
-            serde_json::to_string_pretty(&config)?,
-        )?;
+        config.projects.remove(project_name);
+        save_config(&config)?;
         println!("Project '{}' removed from local config", project_name);
-    } else {
-        println!("Error: {}", response.text().await?);
     }
 
     Ok(())
@@ -0,0 +1,48 @@
+use crate::api::pull::{fetch, fetch_layered};
+use crate::crypto::ContentType;
+use crate::diff::render;
+use std::fs;
+
+/// Compares the server copy of a project with a local file, returning whether they differ.
+/// Like `pull`, the server copy includes the project's base environment unless `with_base`
+/// is false.
+pub async fn diff(
+    api_url: &str,
+    project_name: &str,
+    file_path: &str,
+    show_values: bool,
+    environment: &str,
+    with_base: bool,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let local = fs::read(file_path)?;
+    let pulled = if with_base {
+        fetch_layered(api_url, project_name, environment).await?
+    } else {
+        fetch(api_url, project_name, environment).await?
+    };
+    let Some(pulled) = pulled else {
+        return Ok(false);
+    };
+
+    // Binary files can only be told apart, not diffed.
+    let changes = match (pulled.content_type, std::str::from_utf8(&local)) {
+        (ContentType::Text, Ok(local)) => render(pulled.text(project_name)?, local, show_values),
+        _ if pulled.content == local => None,
+        _ => Some("Binary files differ".to_string()),
+    };
+    match changes {
+        Some(changes) => {
+            println!("--- {} [{}] (server)", project_name, environment);
+            println!("+++ {} (local)", file_path);
+            println!("{}", changes);
+            Ok(true)
+        }
+        None => {
+            println!(
+                "No differences between '{}' and {}",
+                project_name, file_path
+            );
+            Ok(false)
+        }
+    }
+}
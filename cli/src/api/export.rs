@@ -0,0 +1,27 @@
+use crate::api::pull::fetch_layered;
+use crate::format::Format;
+use crate::utils::write_secret_file;
+
+/// Renders a project's variables in another format, to `output_file` or stdout.
+pub async fn export(
+    api_url: &str,
+    project_name: &str,
+    format: Format,
+    output_file: Option<&str>,
+    environment: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(pulled) = fetch_layered(api_url, project_name, environment).await? else {
+        return Ok(());
+    };
+    let rendered = format.render(pulled.text(project_name)?, project_name)?;
+
+    match output_file {
+        Some(output_file) => {
+            write_secret_file(output_file, rendered)?;
+            println!("Exported '{}' to {}", project_name, output_file);
+        }
+        None => print!("{}", rendered),
+    }
+
+    Ok(())
+}
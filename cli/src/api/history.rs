@@ -0,0 +1,118 @@
+use crate::api::http_client;
+use crate::config::{load_config, set_project_revision};
+use crate::crypto::{decrypt_bytes, encrypt_bytes};
+use crate::utils::etag_revision;
+use chrono::DateTime;
+use serde_json::{json, Value};
+
+pub async fn history(
+    api_url: &str,
+    project_name: &str,
+    environment: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let config = load_config()?;
+    let project = config.project(project_name)?;
+    let access_key = project.access_key.as_str();
+
+    let client = http_client(api_url)?;
+    let response = client
+        .get(format!("{}/versions", api_url))
+        .header("X-API-Key", access_key)
+        .header("X-Environment", environment)
+        .send()
+        .await?;
+
+    if response.status().is_success() {
+        let json: Value = response.json().await?;
+        let versions = json
+            .get("versions")
+            .and_then(Value::as_array)
+            .ok_or("No versions found")?;
+        println!("Versions of '{}' [{}]:", project_name, environment);
+        for version in versions {
+            let created_at = version["created_at"]
+                .as_i64()
+                .and_then(|ts| DateTime::from_timestamp(ts, 0))
+                .map(|dt| dt.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+                .unwrap_or_default();
+            println!(
+                "- v{}  {}  {}",
+                version["version"],
+                created_at,
+                version["content_hash"].as_str().unwrap_or_default(),
+            );
+        }
+    } else {
+        println!("Error: {}", response.text().await?);
+    }
+
+    Ok(())
+}
+
+pub async fn rollback(
+    api_url: &str,
+    project_name: &str,
+    version: u64,
+    environment: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let config = load_config()?;
+    let project = config.project(project_name)?;
+    let access_key = project.access_key.as_str();
+    let encryption_key = project.key()?;
+
+    let client = http_client(api_url)?;
+    let response = client
+        .get(format!("{}/versions/{}", api_url, version))
+        .header("X-API-Key", access_key)
+        .header("X-Environment", environment)
+        .send()
+        .await?;
+
+    if !response.status().is_success() {
+        println!("Error: {}", response.text().await?);
+        return Ok(());
+    }
+
+    let json: Value = response.json().await?;
+    let encrypted_content = json["encrypted_content"]
+        .as_str()
+        .ok_or("No content found")?;
+    // Decrypt locally first, so a version we can no longer read is never restored.
+    let (content_type, content) = match decrypt_bytes(encrypted_content, &encryption_key) {
+        Ok(decrypted) => decrypted,
+        Err(e) => {
+            println!("Error: Could not decrypt version {}: {}", version, e);
+            return Ok(());
+        }
+    };
+
+    let response = client
+        .put(format!("{}/update", api_url))
+        .header("X-API-Key", access_key)
+        .header("X-Environment", environment)
+        .json(&json!({
+            "project_id": project_name,
+            "encrypted_content": encrypt_bytes(
+                &content,
+                content_type,
+                &encryption_key
+            )
+        }))
+        .send()
+        .await?;
+
+    if response.status().is_success() {
+        if let Some(revision) = etag_revision(response.headers()) {
+            set_project_revision(project_name, environment, Some(revision))?;
+        }
+        let json: Value = response.json().await?;
+        println!(
+            "Project '{}' rolled back to v{} (saved as v{})",
+            project_name, version, json["version"]
+        );
+    } else {
+        println!("Error: {}", response.text().await?);
+    }
+
+    Ok(())
+}
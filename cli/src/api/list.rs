@@ -0,0 +1,48 @@
+use crate::api::http_client;
+use crate::config::Config;
+use serde_json::Value;
+
+/// Lists the projects in the local config with their environments on their servers,
+/// or only those on `profile`.
+pub async fn list(
+    config: &Config,
+    profile: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(profile) = profile {
+        config.server_url(profile)?;
+    }
+    println!("Projects in local config:");
+    for (project, project_config) in &config.projects {
+        let server = config.project_server(project_config);
+        if profile.is_some_and(|profile| profile != server) {
+            continue;
+        }
+        let api_url = config.resolve_api_url(Some(project), None)?;
+        let access_key = &project_config.access_key;
+
+        let response = http_client(api_url)?
+            .get(format!("{}/environments", api_url))
+            .header("X-API-Key", access_key)
+            .send()
+            .await;
+        let environments = match response {
+            Ok(response) if response.status().is_success() => {
+                response.json::<Value>().await.ok().and_then(|json| {
+                    json["environments"].as_array().map(|environments| {
+                        environments
+                            .iter()
+                            .filter_map(Value::as_str)
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    })
+                })
+            }
+            _ => None,
+        };
+        match environments {
+            Some(environments) => println!("- {} [{}] ({})", project, server, environments),
+            None => println!("- {} [{}] (environments unavailable)", project, server),
+        }
+    }
+    Ok(())
+}
@@ -1,11 +1,43 @@
+use crate::config::server_token;
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+use reqwest::Client;
+
+mod base;
 mod delete;
+mod diff;
+mod export;
+mod history;
+mod list;
 mod pull;
 mod push;
+mod rotate;
+mod run;
 mod share;
 mod update;
+mod vars;
 
+pub use base::{get_base, set_base};
 pub use delete::delete;
+pub use diff::diff;
+pub use export::export;
+pub use history::{history, rollback};
+pub use list::list;
 pub use pull::pull;
 pub use push::push;
+pub use rotate::{resume_rotation, rotate_key};
+pub use run::run;
 pub use share::{getshared, share};
 pub use update::update;
+pub use vars::{get_var, set_var, unset_var};
+
+/// An HTTP client for the server at `api_url`, sending the token of its profile if it has one.
+pub(crate) fn http_client(api_url: &str) -> Result<Client, Box<dyn std::error::Error>> {
+    let Some(token) = server_token(api_url)? else {
+        return Ok(Client::new());
+    };
+    let mut authorization = HeaderValue::from_str(&format!("Bearer {}", token))
+        .map_err(|_| "The server token can only contain visible ASCII characters")?;
+    authorization.set_sensitive(true);
+    let headers = HeaderMap::from_iter([(AUTHORIZATION, authorization)]);
+    Ok(Client::builder().default_headers(headers).build()?)
+}
@@ -1,61 +1,196 @@
-use crate::config::get_or_create_config;
-use crate::crypto::decrypt;
-use base64::{engine::general_purpose, Engine as _};
-use reqwest::Client;
+use crate::api::http_client;
+use crate::bundle::Bundle;
+use crate::config::{load_config, set_project_revision};
+use crate::crypto::{decrypt_bytes, ContentType, CryptoError};
+use crate::dotenv::DotEnv;
+use crate::format::Format;
+use crate::utils::{etag_revision, write_secret_file};
 use serde_json::Value;
-use std::fs;
 use std::path::Path;
 
+/// The decrypted content of a project, with the server revision it was read at.
+pub(crate) struct Pulled {
+    pub content: Vec<u8>,
+    pub content_type: ContentType,
+    pub revision: Option<u64>,
+    /// The environment the project's other environments are layered over, if it has one.
+    pub base: Option<String>,
+}
+
+impl Pulled {
+    /// The content as text, or an error if the project holds a binary file.
+    pub fn text(&self, project_name: &str) -> Result<&str, String> {
+        let binary = || format!("'{}' holds a binary file, not text", project_name);
+        match self.content_type {
+            ContentType::Text => std::str::from_utf8(&self.content).map_err(|_| binary()),
+            ContentType::Binary => Err(binary()),
+        }
+    }
+}
+
 pub async fn pull(
     api_url: &str,
     project_name: &str,
     output_file: &str,
     force: bool,
+    format: Format,
+    environment: &str,
+    with_base: bool,
 ) -> Result<(), Box<dyn std::error::Error>> {
+    let pulled = if with_base {
+        fetch_layered(api_url, project_name, environment).await?
+    } else {
+        fetch(api_url, project_name, environment).await?
+    };
+    let Some(pulled) = pulled else {
+        return Ok(());
+    };
+
+    // A bundle of files is restored as a directory tree under `output_file`.
+    let bundle = match pulled.content_type {
+        ContentType::Text => Bundle::decode(pulled.text(project_name)?),
+        ContentType::Binary => None,
+    };
+    if let Some(bundle) = bundle {
+        if format != Format::Dotenv {
+            return Err("--format can't be used with a project that bundles several files".into());
+        }
+        if Path::new(output_file).is_file() {
+            println!(
+                "Error: {} is a file, but '{}' bundles several files and needs a directory.",
+                output_file, project_name
+            );
+            return Ok(());
+        }
+        if let Err(e) = bundle.extract(Path::new(output_file), force) {
+            println!("Error: {}", e);
+            return Ok(());
+        }
+        if let Some(revision) = pulled.revision {
+            set_project_revision(project_name, environment, Some(revision))?;
+        }
+        println!(
+            "{} files pulled successfully into {}",
+            bundle.paths().len(),
+            output_file
+        );
+        return Ok(());
+    }
+
     if Path::new(output_file).exists() && !force {
         println!("Error: Output file already exists. Use --force to overwrite.");
         return Ok(());
     }
+    // A plain pull keeps the file exactly as it was pushed, byte for byte.
+    let content = match format {
+        Format::Dotenv => pulled.content,
+        format => format
+            .render(pulled.text(project_name)?, project_name)?
+            .into_bytes(),
+    };
+    write_secret_file(output_file, content)?;
+    if let Some(revision) = pulled.revision {
+        set_project_revision(project_name, environment, Some(revision))?;
+    }
+    println!("File pulled successfully and saved to {}", output_file);
 
-    let config = get_or_create_config()?;
-    let project_config = config
-        .get(project_name)
-        .ok_or("Project not found in config")?;
-    let access_key = project_config
-        .get("access_key")
-        .ok_or("No access key found")?
-        .as_str()
-        .unwrap();
-    let encryption_key = project_config
-        .get("encryption_key")
-        .ok_or("No encryption key found")?
-        .as_str()
-        .unwrap();
-    let encryption_key_bytes = general_purpose::STANDARD.decode(encryption_key)?;
+    Ok(())
+}
+
+/// Pulls and decrypts an environment of a project in memory. Server errors are printed
+/// and give `None`.
+pub(crate) async fn fetch(
+    api_url: &str,
+    project_name: &str,
+    environment: &str,
+) -> Result<Option<Pulled>, Box<dyn std::error::Error>> {
+    let config = load_config()?;
+    let project = config.project(project_name)?;
+    let access_key = project.access_key.as_str();
+    let encryption_key = project.key()?;
 
-    let client = Client::new();
+    let client = http_client(api_url)?;
     let response = client
-        .get(&format!("{}/pull", api_url))
+        .get(format!("{}/pull", api_url))
         .header("X-API-Key", access_key)
+        .header("X-Environment", environment)
         .send()
         .await?;
 
-    if response.status().is_success() {
-        let json: Value = response.json().await?;
-        let encrypted_content = json
-            .get("encrypted_content")
-            .ok_or("No content found")?
-            .as_str()
-            .unwrap();
-        let decrypted_content = decrypt(
-            encrypted_content,
-            encryption_key_bytes.as_slice().try_into()?,
-        );
-        fs::write(output_file, decrypted_content)?;
-        println!("File pulled successfully and saved to {}", output_file);
-    } else {
+    // After an interrupted rotation, the server may already use the new keys.
+    let interrupted_rotation = || {
+        if project.pending_encryption_key.is_some() {
+            println!(
+                "A rotation of '{}' was interrupted, finish it with `dotenvpull rotate-key {} --resume`",
+                project_name, project_name
+            );
+        }
+    };
+    if !response.status().is_success() {
         println!("Error: {}", response.text().await?);
+        interrupted_rotation();
+        return Ok(None);
     }
 
-    Ok(())
+    let revision = etag_revision(response.headers());
+    let json: Value = response.json().await?;
+    let base = json["base"].as_str().map(String::from);
+    let encrypted_content = json["encrypted_content"]
+        .as_str()
+        .ok_or("No content found")?;
+    let (content_type, content) = match decrypt_bytes(encrypted_content, &encryption_key) {
+        Ok(decrypted) => decrypted,
+        Err(e) => {
+            println!(
+                "Error: Could not decrypt '{}' [{}]: {}",
+                project_name, environment, e
+            );
+            if e == CryptoError::KeyMismatch {
+                interrupted_rotation();
+            }
+            return Ok(None);
+        }
+    };
+
+    Ok(Some(Pulled {
+        content,
+        content_type,
+        revision,
+        base,
+    }))
+}
+
+/// Like `fetch`, but if the project declares a base environment, the result is the base
+/// with this environment's variables laid over it. The revision is still this environment's.
+pub(crate) async fn fetch_layered(
+    api_url: &str,
+    project_name: &str,
+    environment: &str,
+) -> Result<Option<Pulled>, Box<dyn std::error::Error>> {
+    let Some(overlay) = fetch(api_url, project_name, environment).await? else {
+        return Ok(None);
+    };
+    let Some(base) = overlay.base.clone().filter(|base| base != environment) else {
+        return Ok(Some(overlay));
+    };
+    let Some(base_pulled) = fetch(api_url, project_name, &base).await? else {
+        return Ok(None);
+    };
+
+    let parse = |pulled: &Pulled, environment: &str| {
+        DotEnv::parse(pulled.text(project_name)?).map_err(|e| {
+            format!(
+                "Environment '{}' of '{}' is not a valid .env file, so it can't be layered ({})",
+                environment, project_name, e
+            )
+        })
+    };
+    let mut merged = parse(&base_pulled, &base)?;
+    merged.overlay(&parse(&overlay, environment)?);
+    Ok(Some(Pulled {
+        content: merged.to_string().into_bytes(),
+        content_type: ContentType::Text,
+        revision: overlay.revision,
+        base: overlay.base,
+    }))
 }
@@ -1,8 +1,12 @@
-use crate::config::update_config;
-use crate::crypto::encrypt;
+use crate::api::http_client;
+use crate::bundle::Bundle;
+use crate::config::{load_config, set_project_revision, update_config};
+use crate::crypto::{encrypt_bytes, ContentType};
+use crate::format::Format;
+use crate::utils::etag_revision;
 use base64::{engine::general_purpose, Engine as _};
 use rand::Rng;
-use reqwest::Client;
+use reqwest::{Client, Response, StatusCode};
 use serde_json::json;
 use std::fs;
 
@@ -10,38 +14,134 @@ pub async fn push(
     api_url: &str,
     project_name: &str,
     file_path: &str,
+    from: Format,
+    environment: &str,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let content = fs::read_to_string(file_path)?;
-    let encryption_key: [u8; 32] = rand::thread_rng().gen();
-    let encrypted_content = encrypt(&content, &encryption_key);
+    let (content, content_type) = read_content(file_path, from)?;
 
-    println!("{}", content);
-    println!("{}", api_url);
+    // Pushing to a project that is already set up adds an environment to it,
+    // encrypted with the project's existing key.
+    let config = load_config()?;
+    let existing = match config.projects.get(project_name) {
+        Some(project) => Some((project.access_key.clone(), project.key()?)),
+        None => None,
+    };
+    let encryption_key: [u8; 32] = match &existing {
+        Some((_, encryption_key)) => *encryption_key,
+        None => rand::thread_rng().gen(),
+    };
+    let encrypted_content = encrypt_bytes(&content, content_type, &encryption_key);
 
-    let client = Client::new();
-    let response = client
-        .post(&format!("{}/push", api_url))
-        .json(&json!({
-            "project_id": project_name,
-            "encrypted_content": encrypted_content
-        }))
-        .send()
+    let client = http_client(api_url)?;
+    let access_key = existing.as_ref().map(|(access_key, _)| access_key.as_str());
+    let mut response = send(
+        &client,
+        api_url,
+        project_name,
+        environment,
+        &encrypted_content,
+        access_key,
+    )
+    .await?;
+    // Once its last environment is deleted the project is gone from the server, so it is
+    // created again under a new access key, keeping its encryption key.
+    let mut created = existing.is_none();
+    if !created && response.status() == StatusCode::NOT_FOUND {
+        println!(
+            "'{}' has no environments left on the server, creating it again",
+            project_name
+        );
+        response = send(
+            &client,
+            api_url,
+            project_name,
+            environment,
+            &encrypted_content,
+            None,
+        )
         .await?;
-
-    println!("{}", response.status());
+        created = true;
+    }
 
     if response.status().is_success() {
-        let json = response.json::<serde_json::Value>().await?;
-        let access_key = json["access_key"].as_str().unwrap();
-        update_config(
-            project_name,
-            access_key,
-            &general_purpose::STANDARD.encode(encryption_key),
-        )?;
+        let revision = etag_revision(response.headers());
+        if created {
+            let json = response.json::<serde_json::Value>().await?;
+            let access_key = json["access_key"]
+                .as_str()
+                .ok_or("The server did not return an access key")?;
+            update_config(
+                project_name,
+                api_url,
+                access_key,
+                &general_purpose::STANDARD.encode(encryption_key),
+            )?;
+        }
+        if let Some(revision) = revision {
+            set_project_revision(project_name, environment, Some(revision))?;
+        }
         println!("File pushed successfully");
+        if created && existing.is_some() {
+            println!(
+                "'{}' has a new access key, share it again with everyone who should have access.",
+                project_name
+            );
+        }
     } else {
         println!("Error: {}", response.text().await?);
     }
 
     Ok(())
 }
+
+/// Uploads an environment, to the project of `access_key` or as a new project.
+async fn send(
+    client: &Client,
+    api_url: &str,
+    project_name: &str,
+    environment: &str,
+    encrypted_content: &str,
+    access_key: Option<&str>,
+) -> Result<Response, reqwest::Error> {
+    let mut request = client
+        .post(format!("{}/push", api_url))
+        .header("X-Environment", environment)
+        .json(&json!({
+            "project_id": project_name,
+            "encrypted_content": encrypted_content
+        }));
+    if let Some(access_key) = access_key {
+        request = request.header("X-API-Key", access_key);
+    }
+    request.send().await
+}
+
+/// Reads what to upload: a bundle if `file_path` is a directory or glob pattern,
+/// otherwise the file, converted from `from`. A file that isn't UTF-8 text is uploaded
+/// as-is as a binary file.
+pub(crate) fn read_content(
+    file_path: &str,
+    from: Format,
+) -> Result<(Vec<u8>, ContentType), Box<dyn std::error::Error>> {
+    if let Some(bundle) = Bundle::collect(file_path)? {
+        if from != Format::Dotenv {
+            return Err("--from can only be used with a single file".into());
+        }
+        println!(
+            "Bundling {} files: {}",
+            bundle.paths().len(),
+            bundle.paths().join(", ")
+        );
+        return Ok((bundle.encode().into_bytes(), ContentType::Text));
+    }
+    let data = fs::read(file_path)?;
+    let text = match String::from_utf8(data) {
+        Ok(text) => text,
+        Err(e) if from == Format::Dotenv => return Ok((e.into_bytes(), ContentType::Binary)),
+        Err(_) => return Err(format!("{}: --from needs a text file", file_path).into()),
+    };
+    let content = from
+        .import(&text)
+        .map_err(|e| format!("{}: {}", file_path, e))?;
+    Ok((content.into_bytes(), ContentType::Text))
+}
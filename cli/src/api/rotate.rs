@@ -0,0 +1,254 @@
+use crate::api::http_client;
+use crate::api::pull::fetch;
+use crate::config::{load_config, save_config};
+use crate::crypto::{decrypt_bytes, encrypt_bytes};
+use crate::utils::etag_revision;
+use base64::{engine::general_purpose, Engine as _};
+use rand::Rng;
+use reqwest::Client;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+
+/// Re-encrypts every environment of a project under a fresh encryption key, and optionally
+/// moves it to a new access key, so people who had the old keys lose access.
+pub async fn rotate_key(
+    api_url: &str,
+    project_name: &str,
+    rotate_access_key: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let project = load_config()?.project(project_name)?.clone();
+    if project.pending_encryption_key.is_some() {
+        return Err(format!(
+            "An earlier rotation of '{}' was interrupted, finish it first with `rotate-key {} --resume`",
+            project_name, project_name
+        )
+        .into());
+    }
+    let access_key = project.access_key;
+
+    let client = http_client(api_url)?;
+    let Some(environments) = list_environments(&client, api_url, &access_key).await? else {
+        return Ok(());
+    };
+    if environments.is_empty() {
+        println!(
+            "Error: '{}' has no environments on the server",
+            project_name
+        );
+        return Ok(());
+    }
+
+    let encryption_key: [u8; 32] = rand::thread_rng().gen();
+    let mut secrets = Vec::with_capacity(environments.len());
+    for environment in &environments {
+        let Some(pulled) = fetch(api_url, project_name, environment).await? else {
+            return Ok(());
+        };
+        let revision = pulled
+            .revision
+            .ok_or("The server did not report the current version")?;
+        secrets.push(json!({
+            "environment": environment,
+            "encrypted_content": encrypt_bytes(&pulled.content, pulled.content_type, &encryption_key),
+            "expected_version": revision,
+        }));
+    }
+
+    // Keep the new keys before the server switches to them, so a crash in between can't
+    // leave the project under keys nobody has; `--resume` picks up from there.
+    let encoded_key = general_purpose::STANDARD.encode(encryption_key);
+    let new_access_key = rotate_access_key.then(|| {
+        let bytes: [u8; 32] = rand::thread_rng().gen();
+        bytes
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>()
+    });
+    set_pending_keys(project_name, Some(&encoded_key), new_access_key.as_deref())?;
+
+    let response = client
+        .post(format!("{}/rotate", api_url))
+        .header("X-API-Key", &access_key)
+        .json(&json!({
+            "secrets": secrets,
+            "new_access_key": new_access_key,
+        }))
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        set_pending_keys(project_name, None, None)?;
+        println!("Error: {}", response.text().await?);
+        return Ok(());
+    }
+
+    let json: Value = response.json().await?;
+    let revisions = serde_json::from_value(json["versions"].clone())?;
+    finish_rotation(project_name, revisions)?;
+
+    println!(
+        "Encryption key of '{}' rotated for {} environment(s): {}",
+        project_name,
+        environments.len(),
+        environments.join(", ")
+    );
+    if rotate_access_key {
+        println!("The access key was rotated too.");
+    }
+    println!("Share the project again with everyone who should keep access; older versions were dropped.");
+    Ok(())
+}
+
+/// Finishes a rotation that was interrupted after the new keys were saved: if the server
+/// already switched to them they replace the old ones, otherwise they are dropped.
+pub async fn resume_rotation(
+    api_url: &str,
+    project_name: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let project = load_config()?.project(project_name)?.clone();
+    let Some(pending_key) = project.pending_encryption_key.as_deref() else {
+        return Err(format!("There is no interrupted rotation of '{}'", project_name).into());
+    };
+    let pending_key: [u8; 32] = general_purpose::STANDARD
+        .decode(pending_key)
+        .ok()
+        .and_then(|key| key.try_into().ok())
+        .ok_or("The pending encryption key is not a valid 32-byte key")?;
+
+    // A new access key only holds the project once the rotation went through.
+    let client = http_client(api_url)?;
+    let mut access_key = project.access_key.as_str();
+    if let Some(pending_access_key) = project.pending_access_key.as_deref() {
+        if list_environments(&client, api_url, pending_access_key)
+            .await?
+            .is_some_and(|environments| !environments.is_empty())
+        {
+            access_key = pending_access_key;
+        }
+    }
+    let Some(environments) = list_environments(&client, api_url, access_key).await? else {
+        return Ok(());
+    };
+    if environments.is_empty() {
+        println!(
+            "Error: '{}' has no environments on the server",
+            project_name
+        );
+        return Ok(());
+    }
+
+    let mut revisions = BTreeMap::new();
+    for environment in &environments {
+        let Some((encrypted_content, revision)) =
+            pull_encrypted(&client, api_url, access_key, environment).await?
+        else {
+            return Ok(());
+        };
+        if decrypt_bytes(&encrypted_content, &pending_key).is_err() {
+            set_pending_keys(project_name, None, None)?;
+            println!(
+                "The rotation of '{}' never reached the server, the old keys are still in use. Run rotate-key again.",
+                project_name
+            );
+            return Ok(());
+        }
+        if let Some(revision) = revision {
+            revisions.insert(environment.clone(), revision);
+        }
+    }
+
+    finish_rotation(project_name, revisions)?;
+    println!(
+        "Finished the rotation of '{}' for {} environment(s): {}",
+        project_name,
+        environments.len(),
+        environments.join(", ")
+    );
+    Ok(())
+}
+
+/// Replaces the project's keys with the pending ones, once the server uses them.
+fn finish_rotation(
+    project_name: &str,
+    revisions: BTreeMap<String, u64>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut config = load_config()?;
+    let project = config.project_mut(project_name)?;
+    project.encryption_key = project
+        .pending_encryption_key
+        .take()
+        .ok_or("The new encryption key is missing from the config")?;
+    if let Some(access_key) = project.pending_access_key.take() {
+        project.access_key = access_key;
+    }
+    project.revisions = revisions;
+    save_config(&config)
+}
+
+/// Records the keys a rotation is switching to, or clears them with `None`.
+fn set_pending_keys(
+    project_name: &str,
+    encryption_key: Option<&str>,
+    access_key: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut config = load_config()?;
+    let project = config.project_mut(project_name)?;
+    project.pending_encryption_key = encryption_key.map(String::from);
+    project.pending_access_key = access_key.map(String::from);
+    save_config(&config)
+}
+
+/// The environments of the project behind `access_key`. Server errors are printed and
+/// give `None`, except that an unknown access key has no environments.
+async fn list_environments(
+    client: &Client,
+    api_url: &str,
+    access_key: &str,
+) -> Result<Option<Vec<String>>, Box<dyn std::error::Error>> {
+    let response = client
+        .get(format!("{}/environments", api_url))
+        .header("X-API-Key", access_key)
+        .send()
+        .await?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(Some(Vec::new()));
+    }
+    if !response.status().is_success() {
+        println!("Error: {}", response.text().await?);
+        return Ok(None);
+    }
+    let json: Value = response.json().await?;
+    Ok(Some(
+        json["environments"]
+            .as_array()
+            .ok_or("No environments found")?
+            .iter()
+            .filter_map(|e| e.as_str().map(String::from))
+            .collect(),
+    ))
+}
+
+/// The still encrypted content of an environment, with its revision.
+async fn pull_encrypted(
+    client: &Client,
+    api_url: &str,
+    access_key: &str,
+    environment: &str,
+) -> Result<Option<(String, Option<u64>)>, Box<dyn std::error::Error>> {
+    let response = client
+        .get(format!("{}/pull", api_url))
+        .header("X-API-Key", access_key)
+        .header("X-Environment", environment)
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        println!("Error: {}", response.text().await?);
+        return Ok(None);
+    }
+    let revision = etag_revision(response.headers());
+    let json: Value = response.json().await?;
+    let encrypted_content = json["encrypted_content"]
+        .as_str()
+        .ok_or("No content found")?
+        .to_string();
+    Ok(Some((encrypted_content, revision)))
+}
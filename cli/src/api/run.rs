@@ -0,0 +1,84 @@
+use crate::api::pull::fetch_layered;
+use crate::dotenv::DotEnv;
+use std::env;
+use std::process::ExitStatus;
+use tokio::process::{Child, Command};
+
+/// Runs `command` with the project's variables in its environment, without writing them
+/// to disk. Returns the exit code to pass on, or `None` if the project could not be pulled.
+pub async fn run(
+    api_url: &str,
+    project_name: &str,
+    command: &[String],
+    override_existing: bool,
+    environment: &str,
+) -> Result<Option<i32>, Box<dyn std::error::Error>> {
+    let (program, args) = command.split_first().ok_or("No command given")?;
+    let Some(pulled) = fetch_layered(api_url, project_name, environment).await? else {
+        return Ok(None);
+    };
+    let vars = DotEnv::parse(pulled.text(project_name)?)
+        .map_err(|e| format!("'{}' is not a valid .env file ({})", project_name, e))?
+        .vars();
+
+    let mut child = Command::new(program);
+    child.args(args);
+    for (key, value) in vars {
+        if override_existing || env::var_os(&key).is_none() {
+            child.env(key, value);
+        }
+    }
+    let child = child
+        .spawn()
+        .map_err(|e| format!("Failed to start '{}': {}", program, e))?;
+
+    let status = wait_forwarding_signals(child).await?;
+    Ok(Some(exit_code(status)))
+}
+
+#[cfg(unix)]
+async fn wait_forwarding_signals(mut child: Child) -> std::io::Result<ExitStatus> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    // SIGINT and SIGQUIT from the terminal already reach the child, which shares our
+    // process group, so they are only caught here to keep us alive until it exits.
+    let mut interrupt = signal(SignalKind::interrupt())?;
+    let mut quit = signal(SignalKind::quit())?;
+    let mut terminate = signal(SignalKind::terminate())?;
+    let mut hangup = signal(SignalKind::hangup())?;
+    let mut user1 = signal(SignalKind::user_defined1())?;
+    let mut user2 = signal(SignalKind::user_defined2())?;
+
+    loop {
+        let forward = tokio::select! {
+            status = child.wait() => return status,
+            _ = interrupt.recv() => None,
+            _ = quit.recv() => None,
+            _ = terminate.recv() => Some(libc::SIGTERM),
+            _ = hangup.recv() => Some(libc::SIGHUP),
+            _ = user1.recv() => Some(libc::SIGUSR1),
+            _ = user2.recv() => Some(libc::SIGUSR2),
+        };
+        if let (Some(signal), Some(pid)) = (forward, child.id()) {
+            // SAFETY: `kill` has no memory-safety preconditions.
+            unsafe { libc::kill(pid as libc::pid_t, signal) };
+        }
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_forwarding_signals(mut child: Child) -> std::io::Result<ExitStatus> {
+    child.wait().await
+}
+
+/// The child's exit code, or `128 + signal` like a shell if it was killed by a signal.
+fn exit_code(status: ExitStatus) -> i32 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = status.signal() {
+            return 128 + signal;
+        }
+    }
+    status.code().unwrap_or(1)
+}
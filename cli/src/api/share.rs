@@ -1,59 +1,94 @@
-use crate::config::get_or_create_config;
-use crate::crypto::{decrypt, encrypt};
+use crate::api::http_client;
+use crate::config::{load_config, load_config_locked, save_config, Config, Project};
+use crate::crypto::{
+    decrypt, encrypt, is_passphrase_wrapped, unwrap_with_passphrase, wrap_with_passphrase,
+    CryptoError,
+};
+use crate::share_uri::ShareUri;
 use base64::{engine::general_purpose, Engine as _};
 use rand::Rng;
-use reqwest::Client;
 use serde_json::{json, Value};
-use std::fs;
+use std::collections::BTreeMap;
 
 pub async fn share(
     api_url: &str,
     project_id: &str,
     include_all_projects: bool,
-) -> Result<(), Box<dyn std::error::Error>> {
+    expires_in: Option<u64>,
+    max_uses: Option<u32>,
+    passphrase: Option<&str>,
+) -> Result<Option<ShareUri>, Box<dyn std::error::Error>> {
     println!("Project ID: {}", project_id);
-    let config = get_or_create_config()?;
+    let config = load_config()?;
 
-    let project_config = if include_all_projects {
-        config.clone()
+    let shared = if include_all_projects {
+        // The passphrase lock belongs to this machine; the keys are shared decrypted.
+        Config {
+            lock: None,
+            ..config
+        }
     } else {
-        if !config.as_object().unwrap().contains_key(project_id) {
+        let Some(project) = config.projects.get(project_id) else {
             println!("Error: Project '{}' not found in local config", project_id);
-            return Ok(());
+            return Ok(None);
+        };
+        // Sharing one project leaves out the servers, so the recipient adds it to their
+        // config on the server the share came from, rather than replacing their config.
+        Config {
+            servers: BTreeMap::new(),
+            projects: BTreeMap::from([(
+                project_id.to_string(),
+                Project {
+                    server: None,
+                    ..project.clone()
+                },
+            )]),
+            ..Config::default()
         }
-        json!({ project_id: config[project_id].clone() })
     };
 
     let share_code: [u8; 32] = rand::thread_rng().gen();
     let encryption_key: [u8; 32] = rand::thread_rng().gen();
 
-    let share_code_str = general_purpose::STANDARD.encode(&share_code);
+    let share_code_str = general_purpose::STANDARD.encode(share_code);
 
-    let client = Client::new();
+    let mut encrypted_content = encrypt(&serde_json::to_string(&shared)?, &encryption_key);
+    if let Some(passphrase) = passphrase {
+        encrypted_content = wrap_with_passphrase(&encrypted_content, passphrase);
+    }
+
+    let client = http_client(api_url)?;
     let response = client
-        .post(&format!("{}/share", api_url))
+        .post(format!("{}/share", api_url))
         .json(&json!({
             "project_id": project_id,
-            "encrypted_content": encrypt(&project_config.to_string(), &encryption_key),
+            "encrypted_content": encrypted_content,
             "share_code": share_code_str,
+            "expires_in": expires_in,
+            "max_uses": max_uses,
         }))
         .send()
         .await?;
 
     if response.status().is_success() {
+        let share_uri = ShareUri {
+            api_url: api_url.to_string(),
+            project_id: project_id.to_string(),
+            share_code: share_code_str,
+            encryption_key: general_purpose::STANDARD.encode(encryption_key),
+        };
         println!("Use this command to share the config:");
-        println!(
-            "dotenvpull getshared {} {} {} {}",
-            share_code_str,
-            project_id,
-            api_url,
-            general_purpose::STANDARD.encode(encryption_key),
-        );
+        println!("dotenvpull getshared '{}'", share_uri);
+        if passphrase.is_some() {
+            println!(
+                "The recipient will be asked for the passphrase, send it over a separate channel."
+            );
+        }
+        Ok(Some(share_uri))
     } else {
         println!("Error: {}", response.text().await?);
+        Ok(None)
     }
-
-    Ok(())
 }
 
 pub async fn getshared(
@@ -61,11 +96,12 @@ pub async fn getshared(
     share_code: &str,
     project_id: &str,
     encryption_key: &str,
+    passphrase: Option<&str>,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let client = Client::new();
+    let client = http_client(api_url)?;
 
     let response = client
-        .get(&format!("{}/share", api_url))
+        .get(format!("{}/share", api_url))
         .header("X-Share-Code", share_code)
         .header("X-Project-Id", project_id)
         .send()
@@ -73,35 +109,67 @@ pub async fn getshared(
 
     if response.status().is_success() {
         let json: Value = response.json().await?;
-        let encrypted_content = json
-            .get("encrypted_content")
-            .ok_or("No content found")?
+        let encrypted_content = json["encrypted_content"]
             .as_str()
-            .unwrap();
+            .ok_or("No content found")?;
+
+        let unwrapped_content;
+        let encrypted_content = if is_passphrase_wrapped(encrypted_content) {
+            unwrapped_content = unwrap_share(encrypted_content, passphrase)?;
+            unwrapped_content.as_str()
+        } else {
+            encrypted_content
+        };
 
         let encryption_key_bytes = general_purpose::STANDARD.decode(encryption_key)?;
 
-        let decrypted_content = decrypt(
+        let decrypted_content = match decrypt(
             encrypted_content,
             encryption_key_bytes.as_slice().try_into()?,
-        );
+        ) {
+            Ok(content) => content,
+            Err(e) => {
+                println!(
+                    "Error: Could not decrypt the shared config: {}. Check that the share link was copied in full.",
+                    e
+                );
+                return Ok(());
+            }
+        };
 
-        let decrypted_config: Value = serde_json::from_str(&decrypted_content)?;
+        // Decided before parsing, which gives flat shares from older versions a default
+        // server: only a whole config comes with `api_url` or servers.
+        let raw: Value = serde_json::from_str(&decrypted_content)
+            .map_err(|e| format!("The shared config is not valid: {}", e))?;
+        let is_whole_config = raw.get("api_url").is_some()
+            || raw["servers"]
+                .as_object()
+                .is_some_and(|servers| !servers.is_empty());
+        let shared = Config::parse(&decrypted_content)
+            .map_err(|e| format!("The shared config is not valid: {}", e))?;
 
-        if decrypted_config.get("api_url").is_some() {
-            let _ = fs::remove_file("dotenvpull_config.json");
-            fs::write(
-                "dotenvpull_config.json",
-                serde_json::to_string_pretty(&decrypted_config)?,
-            )?;
+        let mut config = load_config_locked()?;
+        if is_whole_config {
+            // A whole config replaces this one, but a passphrase lock set up here stays.
+            config = Config {
+                lock: config.lock,
+                ..shared
+            };
         } else {
-            let mut config = get_or_create_config()?;
-            config[project_id] = decrypted_config[project_id].clone();
-            fs::write(
-                "dotenvpull_config.json",
-                serde_json::to_string_pretty(&config)?,
-            )?;
+            let project = shared
+                .projects
+                .get(project_id)
+                .ok_or_else(|| format!("The share does not contain '{}'", project_id))?;
+            let server = config.server_for_url(api_url);
+            config.projects.insert(
+                project_id.to_string(),
+                Project {
+                    server: Some(server),
+                    ..project.clone()
+                },
+            );
         }
+        save_config(&config)?;
         println!(
             "Config for project '{}' shared successfully and added to local config.",
             project_id
@@ -112,3 +180,29 @@ pub async fn getshared(
 
     Ok(())
 }
+
+/// Removes the passphrase layer of a share, prompting for the passphrase if none was given.
+fn unwrap_share(
+    encrypted_content: &str,
+    passphrase: Option<&str>,
+) -> Result<String, Box<dyn std::error::Error>> {
+    // Only a wrong passphrase is worth another try; anything else is wrong with the share.
+    let attempt = |passphrase: &str| match unwrap_with_passphrase(encrypted_content, passphrase) {
+        Ok(content) => Ok(Some(content)),
+        Err(CryptoError::KeyMismatch | CryptoError::AuthenticationFailed) => Ok(None),
+        Err(e) => Err(format!("The share can't be unlocked: {}", e)),
+    };
+    if let Some(passphrase) = passphrase {
+        return attempt(passphrase)?.ok_or_else(|| "Wrong passphrase".into());
+    }
+
+    // The share is already claimed at this point, so allow a few attempts.
+    for _ in 0..3 {
+        let passphrase = rpassword::prompt_password("Share passphrase: ")?;
+        if let Some(content) = attempt(&passphrase)? {
+            return Ok(content);
+        }
+        println!("Wrong passphrase, try again.");
+    }
+    Err("Wrong passphrase".into())
+}
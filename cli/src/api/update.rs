@@ -1,47 +1,71 @@
-use crate::config::get_or_create_config;
-use crate::crypto::encrypt;
-use base64::{engine::general_purpose, Engine as _};
-use reqwest::Client;
+use crate::api::base::fetch_base;
+use crate::api::http_client;
+use crate::api::push::read_content;
+use crate::config::{load_config, set_project_revision};
+use crate::crypto::encrypt_bytes;
+use crate::format::Format;
+use crate::utils::etag_revision;
+use reqwest::header::IF_MATCH;
+use reqwest::StatusCode;
 use serde_json::json;
-use std::fs;
 
 pub async fn update(
     api_url: &str,
     project_name: &str,
     file_path: &str,
+    force: bool,
+    from: Format,
+    environment: &str,
+    with_base: bool,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let config = get_or_create_config()?;
-    let project_config = config
-        .get(project_name)
-        .ok_or("Project not found in config")?;
-    let access_key = project_config
-        .get("access_key")
-        .ok_or("No access key found")?
-        .as_str()
-        .unwrap();
-    let encryption_key = project_config
-        .get("encryption_key")
-        .ok_or("No encryption key found")?
-        .as_str()
-        .unwrap();
-    let encryption_key_bytes = general_purpose::STANDARD.decode(encryption_key)?;
+    // A pulled file holds the base's variables too, and uploading it as the environment's
+    // own would pin them there, so layered environments are only updated with `--no-base`.
+    if with_base {
+        let base = fetch_base(api_url, project_name).await?;
+        if let Some(base) = base.filter(|base| base != environment) {
+            println!(
+                "Error: '{}' [{}] is layered over '{}', so a pulled file also holds the variables of '{}'. Pull it with --no-base to edit its own variables, then update with --no-base.",
+                project_name, environment, base, base
+            );
+            return Ok(());
+        }
+    }
+
+    let config = load_config()?;
+    let project = config.project(project_name)?;
+    let access_key = project.access_key.as_str();
+    let encryption_key = project.key()?;
+    let revision = project.revision(environment);
 
-    let client = Client::new();
-    let content = fs::read_to_string(file_path)?;
-    let encrypted_content = encrypt(&content, encryption_key_bytes.as_slice().try_into()?);
+    let client = http_client(api_url)?;
+    let (content, content_type) = read_content(file_path, from)?;
+    let encrypted_content = encrypt_bytes(&content, content_type, &encryption_key);
 
-    let response = client
-        .put(&format!("{}/update", api_url))
+    let mut request = client
+        .put(format!("{}/update", api_url))
         .header("X-API-Key", access_key)
+        .header("X-Environment", environment)
         .json(&json!({
             "project_id": project_name,
             "encrypted_content": encrypted_content
-        }))
-        .send()
-        .await?;
+        }));
+    if let (Some(revision), false) = (revision, force) {
+        request = request.header(IF_MATCH, format!("\"{}\"", revision));
+    }
+    let response = request.send().await?;
 
     if response.status().is_success() {
+        if let Some(revision) = etag_revision(response.headers()) {
+            set_project_revision(project_name, environment, Some(revision))?;
+        }
         println!("File updated successfully");
+    } else if response.status() == StatusCode::PRECONDITION_FAILED {
+        println!(
+            "Error: '{}' was updated on the server since your last pull (you have revision {}, server has {}). Pull it first, or use --force to overwrite.",
+            project_name,
+            revision.unwrap_or_default(),
+            etag_revision(response.headers()).map_or("unknown".to_string(), |r| r.to_string()),
+        );
     } else {
         println!("Error: {}", response.text().await?);
     }
@@ -0,0 +1,125 @@
+use crate::api::http_client;
+use crate::api::pull::{fetch, fetch_layered};
+use crate::config::{load_config, set_project_revision};
+use crate::crypto::encrypt;
+use crate::dotenv::{is_valid_key, DotEnv};
+use crate::utils::etag_revision;
+use reqwest::header::IF_MATCH;
+use reqwest::StatusCode;
+use serde_json::json;
+
+/// Prints a single variable of a project, including ones inherited from its base
+/// environment, and returns it.
+pub async fn get_var(
+    api_url: &str,
+    project_name: &str,
+    key: &str,
+    environment: &str,
+) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let Some(pulled) = fetch_layered(api_url, project_name, environment).await? else {
+        return Ok(None);
+    };
+    let env = DotEnv::parse(pulled.text(project_name)?)
+        .map_err(|e| format!("'{}' is not a valid .env file ({})", project_name, e))?;
+    let value = env
+        .get(key)
+        .ok_or_else(|| format!("'{}' is not set in '{}'", key, project_name))?;
+    println!("{}", value);
+    Ok(Some(value.to_string()))
+}
+
+pub async fn set_var(
+    api_url: &str,
+    project_name: &str,
+    key: &str,
+    value: &str,
+    environment: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if !is_valid_key(key) {
+        return Err(format!("'{}' is not a valid variable name", key).into());
+    }
+    let Some((mut env, revision)) = fetch_dotenv(api_url, project_name, environment).await? else {
+        return Ok(());
+    };
+    env.set(key, value);
+    if save(api_url, project_name, environment, &env, revision).await? {
+        println!("Set {} in '{}'", key, project_name);
+    }
+    Ok(())
+}
+
+pub async fn unset_var(
+    api_url: &str,
+    project_name: &str,
+    key: &str,
+    environment: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let Some((mut env, revision)) = fetch_dotenv(api_url, project_name, environment).await? else {
+        return Ok(());
+    };
+    if !env.unset(key) {
+        return Err(format!("'{}' is not set in '{}'", key, project_name).into());
+    }
+    if save(api_url, project_name, environment, &env, revision).await? {
+        println!("Removed {} from '{}'", key, project_name);
+    }
+    Ok(())
+}
+
+async fn fetch_dotenv(
+    api_url: &str,
+    project_name: &str,
+    environment: &str,
+) -> Result<Option<(DotEnv, Option<u64>)>, Box<dyn std::error::Error>> {
+    let Some(pulled) = fetch(api_url, project_name, environment).await? else {
+        return Ok(None);
+    };
+    let env = DotEnv::parse(pulled.text(project_name)?)
+        .map_err(|e| format!("'{}' is not a valid .env file ({})", project_name, e))?;
+    Ok(Some((env, pulled.revision)))
+}
+
+/// Encrypts and uploads the edited file, guarded by the revision it was read at.
+async fn save(
+    api_url: &str,
+    project_name: &str,
+    environment: &str,
+    env: &DotEnv,
+    revision: Option<u64>,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let config = load_config()?;
+    let project = config.project(project_name)?;
+    let access_key = project.access_key.as_str();
+    let encryption_key = project.key()?;
+
+    let client = http_client(api_url)?;
+    let encrypted_content = encrypt(&env.to_string(), &encryption_key);
+    let mut request = client
+        .put(format!("{}/update", api_url))
+        .header("X-API-Key", access_key)
+        .header("X-Environment", environment)
+        .json(&json!({
+            "project_id": project_name,
+            "encrypted_content": encrypted_content
+        }));
+    if let Some(revision) = revision {
+        request = request.header(IF_MATCH, format!("\"{}\"", revision));
+    }
+    let response = request.send().await?;
+
+    if response.status().is_success() {
+        if let Some(revision) = etag_revision(response.headers()) {
+            set_project_revision(project_name, environment, Some(revision))?;
+        }
+        Ok(true)
+    } else if response.status() == StatusCode::PRECONDITION_FAILED {
+        println!(
+            "Error: '{}' changed on the server while it was being edited. Try again.",
+            project_name
+        );
+        Ok(false)
+    } else {
+        println!("Error: {}", response.text().await?);
+        Ok(false)
+    }
+}
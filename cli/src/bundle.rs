@@ -0,0 +1,144 @@
+use crate::utils::write_secret_file;
+use base64::{engine::general_purpose, Engine as _};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// Marks pushed content as a bundle of files rather than a single file.
+const BUNDLE_FORMAT: &str = "dotenvpull-bundle/1";
+
+/// Several files packed into one project, with their paths relative to a common root.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Bundle {
+    format: String,
+    files: Vec<BundleFile>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct BundleFile {
+    /// Relative path with `/` separators.
+    path: String,
+    /// Base64 of the file's bytes.
+    content: String,
+}
+
+impl Bundle {
+    /// Collects the files named by `input` if it is a directory or a glob pattern such as
+    /// `'**/*.env'`. Returns `None` for a plain file path.
+    pub fn collect(input: &str) -> Result<Option<Bundle>, String> {
+        let (root, paths) = if Path::new(input).is_dir() {
+            let root = PathBuf::from(input);
+            let mut paths = Vec::new();
+            walk(&root, &mut paths).map_err(|e| format!("{}: {}", input, e))?;
+            (root, paths)
+        } else if input.contains(['*', '?', '[']) {
+            let paths = glob::glob(input)
+                .map_err(|e| format!("Invalid pattern '{}': {}", input, e))?
+                .filter_map(Result::ok)
+                .filter(|path| path.is_file())
+                .collect();
+            (pattern_root(input), paths)
+        } else {
+            return Ok(None);
+        };
+
+        let mut files = Vec::new();
+        for path in paths {
+            let relative = path.strip_prefix(&root).unwrap_or(&path);
+            let relative = relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            let content = fs::read(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+            files.push(BundleFile {
+                path: relative,
+                content: general_purpose::STANDARD.encode(content),
+            });
+        }
+        if files.is_empty() {
+            return Err(format!("No files found in '{}'", input));
+        }
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+
+        Ok(Some(Bundle {
+            format: BUNDLE_FORMAT.to_string(),
+            files,
+        }))
+    }
+
+    /// Reads pulled content back as a bundle, or `None` if it is a single file.
+    pub fn decode(content: &str) -> Option<Bundle> {
+        serde_json::from_str::<Bundle>(content)
+            .ok()
+            .filter(|bundle| bundle.format == BUNDLE_FORMAT)
+    }
+
+    pub fn encode(&self) -> String {
+        serde_json::to_string(self).expect("bundle serializes")
+    }
+
+    /// The relative paths of the files in the bundle.
+    pub fn paths(&self) -> Vec<&str> {
+        self.files.iter().map(|f| f.path.as_str()).collect()
+    }
+
+    /// Writes every file under `dir`, refusing to replace existing files unless `force`
+    /// is set. Paths that would escape `dir` are rejected before anything is written.
+    pub fn extract(&self, dir: &Path, force: bool) -> Result<(), String> {
+        let mut targets = Vec::new();
+        for file in &self.files {
+            let relative = Path::new(&file.path);
+            let safe = relative
+                .components()
+                .all(|c| matches!(c, Component::Normal(_)));
+            if !safe {
+                return Err(format!(
+                    "Refusing to write '{}' outside of the output directory",
+                    file.path
+                ));
+            }
+            let target = dir.join(relative);
+            if target.exists() && !force {
+                return Err(format!(
+                    "{} already exists. Use --force to overwrite.",
+                    target.display()
+                ));
+            }
+            let content = general_purpose::STANDARD
+                .decode(&file.content)
+                .map_err(|_| format!("'{}' in the bundle is corrupted", file.path))?;
+            targets.push((target, content));
+        }
+
+        for (target, content) in targets {
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent).map_err(|e| format!("{}: {}", parent.display(), e))?;
+            }
+            write_secret_file(&target, content)
+                .map_err(|e| format!("{}: {}", target.display(), e))?;
+        }
+        Ok(())
+    }
+}
+
+fn walk(dir: &Path, paths: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            walk(&path, paths)?;
+        } else if path.is_file() {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// The directory part of a glob pattern before its first wildcard, which bundled
+/// paths are stored relative to.
+fn pattern_root(pattern: &str) -> PathBuf {
+    Path::new(pattern)
+        .components()
+        .take_while(|c| !c.as_os_str().to_string_lossy().contains(['*', '?', '[']))
+        .collect()
+}
@@ -1,41 +1,256 @@
-use crate::api::{delete, getshared, pull, share, update};
-use crate::config::get_or_create_config;
-use crate::utils::list_projects;
+use crate::api::{
+    delete, diff, export, get_base, get_var, getshared, history, list, pull, resume_rotation,
+    rollback, rotate_key, run, set_base, set_var, share, unset_var, update,
+};
+use crate::config::{
+    config_path, forget_passphrase, load_config, load_config_locked, lock_config,
+    remove_config_lock, remove_server, set_config_path, set_default_server, set_server,
+    DEFAULT_CACHE_TIMEOUT, DEFAULT_ENVIRONMENT,
+};
+use crate::format::Format;
+use crate::share_uri::ShareUri;
+use crate::utils::parse_duration;
 use clap::{App, Arg, SubCommand};
 
+/// The `--env` option shared by every subcommand that works on a project.
+fn env_arg() -> Arg<'static> {
+    Arg::with_name("env")
+        .long("env")
+        .short('e')
+        .takes_value(true)
+        .help("The environment of the project to use (default: \"default\")")
+}
+
 pub async fn run_cli() -> Result<(), Box<dyn std::error::Error>> {
     let matches = App::new("DotEnvPull")
         .version("1.0")
         .author("Ihor Savenko (@denver-code)")
         .about("Manages .env files")
+        .arg(
+            Arg::with_name("config")
+                .long("config")
+                .takes_value(true)
+                .global(true)
+                .help("The config file to use, instead of the nearest dotenvpull_config.json or the global config (also DOTENVPULL_CONFIG)"),
+        )
+        .arg(
+            Arg::with_name("profile")
+                .long("profile")
+                .short('p')
+                .takes_value(true)
+                .global(true)
+                .help("The server profile to use for new projects and shares (default: the default profile); existing projects always use their own"),
+        )
         .subcommand(
             SubCommand::with_name("push")
                 .about("Push a .env or config file to the server")
                 .arg(Arg::with_name("project_name").required(true))
-                .arg(Arg::with_name("file_path").required(true)),
+                .arg(env_arg())
+                .arg(
+                    Arg::with_name("file_path")
+                        .required(true)
+                        .help("A file, a directory, or a quoted glob pattern like '**/*.env' to push several files as one bundle"),
+                )
+                .arg(
+                    Arg::with_name("from")
+                        .long("from")
+                        .takes_value(true)
+                        .possible_values(Format::IMPORT_NAMES)
+                        .help("Convert the file from another format into .env variables before uploading"),
+                ),
         )
         .subcommand(
             SubCommand::with_name("pull")
                 .about("Pull a .env or config file from the server")
                 .arg(Arg::with_name("project_name").required(true))
-                .arg(Arg::with_name("output_file").required(true))
+                .arg(env_arg())
+                .arg(
+                    Arg::with_name("output_file")
+                        .required(true)
+                        .help("The file to write, or the directory to restore a bundle of files into"),
+                )
                 .arg(
                     Arg::with_name("force")
                         .long("force")
                         .short('f')
                         .help("Overwrite the output file if it already exists"),
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .takes_value(true)
+                        .possible_values(Format::NAMES)
+                        .help("Write the variables in another format instead of the file as pushed"),
+                )
+                .arg(
+                    Arg::with_name("no-base")
+                        .long("no-base")
+                        .help("Pull only the environment itself, without the project's base environment under it"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("base")
+                .about("Show or set the environment that a project's other environments are layered over")
+                .arg(Arg::with_name("project_name").required(true))
+                .arg(Arg::with_name("environment"))
+                .arg(
+                    Arg::with_name("clear")
+                        .long("clear")
+                        .conflicts_with("environment")
+                        .help("Stop layering environments over a base"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("export")
+                .about("Print a project's variables as a shell script, JSON, YAML, Docker env-file, systemd EnvironmentFile or Kubernetes Secret")
+                .arg(Arg::with_name("project_name").required(true))
+                .arg(env_arg())
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .takes_value(true)
+                        .possible_values(Format::NAMES)
+                        .default_value("shell"),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .long("output")
+                        .short('o')
+                        .takes_value(true)
+                        .help("Write to a file instead of stdout"),
                 ),
         )
         .subcommand(
             SubCommand::with_name("update")
                 .about("Update an existing .env or config file on the server")
                 .arg(Arg::with_name("project_name").required(true))
-                .arg(Arg::with_name("file_path").required(true)),
+                .arg(env_arg())
+                .arg(
+                    Arg::with_name("file_path")
+                        .required(true)
+                        .help("A file, a directory, or a quoted glob pattern like '**/*.env' to push several files as one bundle"),
+                )
+                .arg(
+                    Arg::with_name("force")
+                        .long("force")
+                        .short('f')
+                        .help("Overwrite the server copy even if it changed since your last pull"),
+                )
+                .arg(
+                    Arg::with_name("from")
+                        .long("from")
+                        .takes_value(true)
+                        .possible_values(Format::IMPORT_NAMES)
+                        .help("Convert the file from another format into .env variables before uploading"),
+                )
+                .arg(
+                    Arg::with_name("no-base")
+                        .long("no-base")
+                        .help("Upload the file as the environment's own variables, even though the project has a base environment"),
+                ),
         )
         .subcommand(
             SubCommand::with_name("delete")
-                .about("Delete a .env or config file from the server")
-                .arg(Arg::with_name("project_name").required(true)),
+                .about("Delete a project, or one of its environments, from the server")
+                .arg(Arg::with_name("project_name").required(true))
+                .arg(
+                    Arg::with_name("env")
+                        .long("env")
+                        .short('e')
+                        .takes_value(true)
+                        .help("Delete only this environment instead of the whole project"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("diff")
+                .about("Show what differs between the server copy of a project and a local file")
+                .arg(Arg::with_name("project_name").required(true))
+                .arg(env_arg())
+                .arg(Arg::with_name("file_path").required(true))
+                .arg(
+                    Arg::with_name("show-values")
+                        .long("show-values")
+                        .help("Print variable values instead of masking them"),
+                )
+                .arg(
+                    Arg::with_name("no-base")
+                        .long("no-base")
+                        .help("Compare with the environment itself, without the project's base environment under it"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("get")
+                .about("Print a single variable of a project")
+                .arg(Arg::with_name("project_name").required(true))
+                .arg(env_arg())
+                .arg(Arg::with_name("key").required(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("set")
+                .about("Set a single variable of a project on the server")
+                .arg(Arg::with_name("project_name").required(true))
+                .arg(env_arg())
+                .arg(Arg::with_name("assignment").required(true).help("KEY=VALUE")),
+        )
+        .subcommand(
+            SubCommand::with_name("unset")
+                .about("Remove a single variable of a project on the server")
+                .arg(Arg::with_name("project_name").required(true))
+                .arg(env_arg())
+                .arg(Arg::with_name("key").required(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("run")
+                .about("Run a command with a project's variables in its environment, without writing them to disk")
+                .arg(Arg::with_name("project_name").required(true))
+                .arg(env_arg())
+                .arg(
+                    Arg::with_name("override")
+                        .long("override")
+                        .help("Let the project's variables replace ones already set in the environment (default)"),
+                )
+                .arg(
+                    Arg::with_name("no-override")
+                        .long("no-override")
+                        .conflicts_with("override")
+                        .help("Keep variables already set in the environment"),
+                )
+                .arg(
+                    Arg::with_name("command")
+                        .required(true)
+                        .multiple_values(true)
+                        .last(true)
+                        .help("The command to run, after --"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("history")
+                .about("List the stored versions of a project")
+                .arg(Arg::with_name("project_name").required(true))
+                .arg(env_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("rollback")
+                .about("Restore an earlier version of a project as its latest version")
+                .arg(Arg::with_name("project_name").required(true))
+                .arg(env_arg())
+                .arg(Arg::with_name("version").required(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("rotate-key")
+                .about("Re-encrypt every environment of a project with a new encryption key")
+                .arg(Arg::with_name("project_name").required(true))
+                .arg(
+                    Arg::with_name("access-key")
+                        .long("access-key")
+                        .help("Also move the project to a new server access key"),
+                )
+                .arg(
+                    Arg::with_name("resume")
+                        .long("resume")
+                        .conflicts_with("access-key")
+                        .help("Finish a rotation that was interrupted before the new keys were saved"),
+                ),
         )
         .subcommand(
             SubCommand::with_name("share")
@@ -46,60 +261,393 @@ pub async fn run_cli() -> Result<(), Box<dyn std::error::Error>> {
                         .long("include-all-projects")
                         .short('a')
                         .help("Use this flag if you wish to share all projects (files) inside of your config"),
+                )
+                .arg(
+                    Arg::with_name("expires-in")
+                        .long("expires-in")
+                        .takes_value(true)
+                        .help("How long the share stays valid, e.g. 30m, 1h or 7d"),
+                )
+                .arg(
+                    Arg::with_name("max-uses")
+                        .long("max-uses")
+                        .takes_value(true)
+                        .help("How many times the share can be claimed (default: 1)"),
+                )
+                .arg(
+                    Arg::with_name("passphrase")
+                        .long("passphrase")
+                        .help("Also protect the share with a passphrase, which the recipient is asked for"),
                 ),
         )
         .subcommand(
             SubCommand::with_name("getshared")
                 .about("Pull a shared .env or config file from the server")
-                .arg(Arg::with_name("share_code").required(true))
-                .arg(Arg::with_name("project_id").required(true))
-                .arg(Arg::with_name("api_url").required(true))
-                .arg(Arg::with_name("encryption_key").required(true)),
+                .arg(
+                    Arg::with_name("share_link")
+                        .required(true)
+                        .help("The dotenvpull:// link printed by `share`, or the share code in the old four-argument form"),
+                )
+                .arg(Arg::with_name("project_id").requires_all(&["api_url", "encryption_key"]))
+                .arg(Arg::with_name("api_url"))
+                .arg(Arg::with_name("encryption_key")),
+        )
+        .subcommand(
+            SubCommand::with_name("lock")
+                .about("Protect the keys in the local config with a master passphrase")
+                .arg(
+                    Arg::with_name("cache-timeout")
+                        .long("cache-timeout")
+                        .takes_value(true)
+                        .help("How long the passphrase is remembered after unlocking, e.g. 30m (default: 15m, 0 to always ask); between commands only if XDG_RUNTIME_DIR is set"),
+                )
+                .arg(
+                    Arg::with_name("forget")
+                        .long("forget")
+                        .conflicts_with_all(&["remove", "cache-timeout"])
+                        .help("Forget the remembered passphrase now"),
+                )
+                .arg(
+                    Arg::with_name("remove")
+                        .long("remove")
+                        .conflicts_with("cache-timeout")
+                        .help("Remove the passphrase and store the keys unencrypted again"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("profile")
+                .about("Manage the server profiles in the config")
+                .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(SubCommand::with_name("list").about("List the server profiles"))
+                .subcommand(
+                    SubCommand::with_name("add")
+                        .about("Add a server profile, or change the URL and token of one")
+                        .arg(Arg::with_name("name").required(true))
+                        .arg(Arg::with_name("api_url").required(true))
+                        .arg(
+                            Arg::with_name("token")
+                                .long("token")
+                                .help("Ask for a token to send as a bearer token with every request to the server"),
+                        )
+                        .arg(
+                            Arg::with_name("default")
+                                .long("default")
+                                .help("Make it the default profile"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("remove")
+                        .about("Remove a server profile no project lives on")
+                        .arg(Arg::with_name("name").required(true)),
+                )
+                .subcommand(
+                    SubCommand::with_name("default")
+                        .about("Set the profile used when neither --profile nor a project names one")
+                        .arg(Arg::with_name("name").required(true)),
+                ),
         )
-        .subcommand(SubCommand::with_name("list").about("List all projects in the local config"))
+        .subcommand(SubCommand::with_name("list").about("List all projects in the local config with their environments"))
         .get_matches();
 
-    let config = get_or_create_config()?;
-    let api_url = config["api_url"]
-        .as_str()
-        .unwrap_or("http://localhost:8080")
-        .to_string();
+    set_config_path(matches.value_of("config").map(std::path::Path::new));
+    // The keys aren't needed to find the server, so a locked config is only unlocked by the
+    // commands that use them.
+    let config = load_config_locked()?;
+    let profile = matches.value_of("profile");
+    // Commands on a project go to the server it lives on.
+    let project_name = match matches.subcommand() {
+        Some(("share", sub_m)) => sub_m.value_of("project_id"),
+        Some(("getshared" | "list" | "lock" | "profile", _)) | None => None,
+        Some((_, sub_m)) => sub_m.value_of("project_name"),
+    };
+    let api_url = config.resolve_api_url(project_name, profile)?.to_string();
 
     match matches.subcommand() {
         Some(("push", sub_m)) => {
             let project_name = sub_m.value_of("project_name").unwrap();
+            let environment = sub_m.value_of("env").unwrap_or(DEFAULT_ENVIRONMENT);
             let file_path = sub_m.value_of("file_path").unwrap();
-            crate::api::push(&api_url, project_name, file_path).await?;
+            let from = sub_m
+                .value_of("from")
+                .map_or(Ok(Format::Dotenv), str::parse)?;
+            crate::api::push(&api_url, project_name, file_path, from, environment).await?;
         }
         Some(("pull", sub_m)) => {
             let project_name = sub_m.value_of("project_name").unwrap();
+            let environment = sub_m.value_of("env").unwrap_or(DEFAULT_ENVIRONMENT);
             let output_file = sub_m.value_of("output_file").unwrap();
             let force = sub_m.is_present("force");
-            pull(&api_url, project_name, output_file, force).await?;
+            let format = sub_m
+                .value_of("format")
+                .map_or(Ok(Format::Dotenv), str::parse)?;
+            let with_base = !sub_m.is_present("no-base");
+            pull(
+                &api_url,
+                project_name,
+                output_file,
+                force,
+                format,
+                environment,
+                with_base,
+            )
+            .await?;
+        }
+        Some(("base", sub_m)) => {
+            let project_name = sub_m.value_of("project_name").unwrap();
+            if sub_m.is_present("clear") {
+                set_base(&api_url, project_name, None).await?;
+            } else if let Some(environment) = sub_m.value_of("environment") {
+                set_base(&api_url, project_name, Some(environment)).await?;
+            } else {
+                get_base(&api_url, project_name).await?;
+            }
+        }
+        Some(("export", sub_m)) => {
+            let project_name = sub_m.value_of("project_name").unwrap();
+            let environment = sub_m.value_of("env").unwrap_or(DEFAULT_ENVIRONMENT);
+            let format = sub_m.value_of("format").unwrap().parse()?;
+            let output_file = sub_m.value_of("output");
+            export(&api_url, project_name, format, output_file, environment).await?;
         }
         Some(("update", sub_m)) => {
             let project_name = sub_m.value_of("project_name").unwrap();
+            let environment = sub_m.value_of("env").unwrap_or(DEFAULT_ENVIRONMENT);
             let file_path = sub_m.value_of("file_path").unwrap();
-            update(&api_url, project_name, file_path).await?;
+            let force = sub_m.is_present("force");
+            let from = sub_m
+                .value_of("from")
+                .map_or(Ok(Format::Dotenv), str::parse)?;
+            let with_base = !sub_m.is_present("no-base");
+            update(
+                &api_url,
+                project_name,
+                file_path,
+                force,
+                from,
+                environment,
+                with_base,
+            )
+            .await?;
         }
         Some(("delete", sub_m)) => {
             let project_name = sub_m.value_of("project_name").unwrap();
-            delete(&api_url, project_name).await?;
+            let environment = sub_m.value_of("env");
+            delete(&api_url, project_name, environment).await?;
+        }
+        Some(("diff", sub_m)) => {
+            let project_name = sub_m.value_of("project_name").unwrap();
+            let environment = sub_m.value_of("env").unwrap_or(DEFAULT_ENVIRONMENT);
+            let file_path = sub_m.value_of("file_path").unwrap();
+            let show_values = sub_m.is_present("show-values");
+            let with_base = !sub_m.is_present("no-base");
+            diff(
+                &api_url,
+                project_name,
+                file_path,
+                show_values,
+                environment,
+                with_base,
+            )
+            .await?;
+        }
+        Some(("get", sub_m)) => {
+            let project_name = sub_m.value_of("project_name").unwrap();
+            let environment = sub_m.value_of("env").unwrap_or(DEFAULT_ENVIRONMENT);
+            let key = sub_m.value_of("key").unwrap();
+            get_var(&api_url, project_name, key, environment).await?;
+        }
+        Some(("set", sub_m)) => {
+            let project_name = sub_m.value_of("project_name").unwrap();
+            let environment = sub_m.value_of("env").unwrap_or(DEFAULT_ENVIRONMENT);
+            let (key, value) = sub_m
+                .value_of("assignment")
+                .unwrap()
+                .split_once('=')
+                .ok_or("Expected KEY=VALUE")?;
+            set_var(&api_url, project_name, key, value, environment).await?;
+        }
+        Some(("unset", sub_m)) => {
+            let project_name = sub_m.value_of("project_name").unwrap();
+            let environment = sub_m.value_of("env").unwrap_or(DEFAULT_ENVIRONMENT);
+            let key = sub_m.value_of("key").unwrap();
+            unset_var(&api_url, project_name, key, environment).await?;
+        }
+        Some(("run", sub_m)) => {
+            let project_name = sub_m.value_of("project_name").unwrap();
+            let environment = sub_m.value_of("env").unwrap_or(DEFAULT_ENVIRONMENT);
+            let command: Vec<String> = sub_m
+                .values_of("command")
+                .unwrap()
+                .map(String::from)
+                .collect();
+            let override_existing = !sub_m.is_present("no-override");
+            let code = run(
+                &api_url,
+                project_name,
+                &command,
+                override_existing,
+                environment,
+            )
+            .await?;
+            std::process::exit(code.unwrap_or(1));
+        }
+        Some(("history", sub_m)) => {
+            let project_name = sub_m.value_of("project_name").unwrap();
+            let environment = sub_m.value_of("env").unwrap_or(DEFAULT_ENVIRONMENT);
+            history(&api_url, project_name, environment).await?;
+        }
+        Some(("rollback", sub_m)) => {
+            let project_name = sub_m.value_of("project_name").unwrap();
+            let environment = sub_m.value_of("env").unwrap_or(DEFAULT_ENVIRONMENT);
+            let version = sub_m
+                .value_of("version")
+                .unwrap()
+                .trim_start_matches('v')
+                .parse::<u64>()
+                .map_err(|_| "Version must be a number")?;
+            rollback(&api_url, project_name, version, environment).await?;
+        }
+        Some(("rotate-key", sub_m)) => {
+            let project_name = sub_m.value_of("project_name").unwrap();
+            if sub_m.is_present("resume") {
+                resume_rotation(&api_url, project_name).await?;
+            } else {
+                rotate_key(&api_url, project_name, sub_m.is_present("access-key")).await?;
+            }
+        }
+        Some(("lock", sub_m)) => {
+            if sub_m.is_present("forget") {
+                forget_passphrase()?;
+                println!("Passphrase forgotten");
+            } else if sub_m.is_present("remove") {
+                remove_config_lock()?;
+                println!("Passphrase removed, the keys in the config are no longer encrypted");
+            } else if config.lock.is_some() {
+                return Err(format!("{} is already locked", config_path().display()).into());
+            } else {
+                let cache_timeout = sub_m
+                    .value_of("cache-timeout")
+                    .map(parse_duration)
+                    .transpose()?
+                    .unwrap_or(DEFAULT_CACHE_TIMEOUT);
+                let passphrase = match std::env::var("DOTENVPULL_PASSPHRASE") {
+                    Ok(passphrase) => passphrase,
+                    Err(_) => {
+                        let passphrase = rpassword::prompt_password("New passphrase: ")?;
+                        if rpassword::prompt_password("Repeat passphrase: ")? != passphrase {
+                            return Err("Passphrases do not match".into());
+                        }
+                        passphrase
+                    }
+                };
+                if passphrase.is_empty() {
+                    return Err("Passphrase must not be empty".into());
+                }
+                lock_config(&passphrase, cache_timeout)?;
+                println!("The keys in the config are now encrypted with your passphrase");
+            }
         }
         Some(("list", _)) => {
-            list_projects(&config);
+            list(&load_config()?, profile).await?;
         }
+        Some(("profile", sub_m)) => match sub_m.subcommand() {
+            Some(("add", sub_m)) => {
+                let name = sub_m.value_of("name").unwrap();
+                let token = if sub_m.is_present("token") {
+                    let token = rpassword::prompt_password(format!("Token for '{}': ", name))?;
+                    if token.is_empty() {
+                        return Err("Token must not be empty".into());
+                    }
+                    Some(token)
+                } else {
+                    None
+                };
+                set_server(name, sub_m.value_of("api_url").unwrap(), token.as_deref())?;
+                if sub_m.is_present("default") {
+                    set_default_server(name)?;
+                }
+                println!("Profile '{}' saved", name);
+            }
+            Some(("remove", sub_m)) => {
+                let name = sub_m.value_of("name").unwrap();
+                remove_server(name)?;
+                println!("Profile '{}' removed", name);
+            }
+            Some(("default", sub_m)) => {
+                let name = sub_m.value_of("name").unwrap();
+                set_default_server(name)?;
+                println!("'{}' is now the default profile", name);
+            }
+            _ => {
+                for (name, server) in &config.servers {
+                    let marker = if *name == config.defaults.server {
+                        " (default)"
+                    } else {
+                        ""
+                    };
+                    let token = if server.token.is_some() {
+                        " (token)"
+                    } else {
+                        ""
+                    };
+                    println!("- {}: {}{}{}", name, server.api_url, token, marker);
+                }
+            }
+        },
         Some(("share", sub_m)) => {
             let project_id = sub_m.value_of("project_id").unwrap();
             let include_all_projects = sub_m.is_present("include-all-projects");
-            share(&api_url, project_id, include_all_projects).await?;
+            let expires_in = sub_m
+                .value_of("expires-in")
+                .map(parse_duration)
+                .transpose()?;
+            let max_uses = sub_m
+                .value_of("max-uses")
+                .map(|n| n.parse::<u32>())
+                .transpose()
+                .map_err(|_| "--max-uses must be a positive number")?;
+            let passphrase = if sub_m.is_present("passphrase") {
+                let passphrase = rpassword::prompt_password("Share passphrase: ")?;
+                if passphrase.is_empty() {
+                    return Err("Passphrase must not be empty".into());
+                }
+                if rpassword::prompt_password("Repeat passphrase: ")? != passphrase {
+                    return Err("Passphrases do not match".into());
+                }
+                Some(passphrase)
+            } else {
+                None
+            };
+            share(
+                &api_url,
+                project_id,
+                include_all_projects,
+                expires_in,
+                max_uses,
+                passphrase.as_deref(),
+            )
+            .await?;
         }
         Some(("getshared", sub_m)) => {
-            let share_code = sub_m.value_of("share_code").unwrap();
-            let project_id = sub_m.value_of("project_id").unwrap();
-            let api_url = sub_m.value_of("api_url").unwrap();
-            let encryption_key = sub_m.value_of("encryption_key").unwrap();
-            getshared(api_url, share_code, project_id, encryption_key).await?;
+            let share_link = sub_m.value_of("share_link").unwrap();
+            let share_uri = match sub_m.value_of("project_id") {
+                // Old form: getshared <share_code> <project_id> <api_url> <encryption_key>
+                Some(project_id) => ShareUri {
+                    api_url: sub_m.value_of("api_url").unwrap().to_string(),
+                    project_id: project_id.to_string(),
+                    share_code: share_link.to_string(),
+                    encryption_key: sub_m.value_of("encryption_key").unwrap().to_string(),
+                },
+                None => ShareUri::parse(share_link)?,
+            };
+            getshared(
+                &share_uri.api_url,
+                &share_uri.share_code,
+                &share_uri.project_id,
+                &share_uri.encryption_key,
+                None,
+            )
+            .await?;
         }
         _ => println!("Please use a valid subcommand. Use --help for more information."),
     }
@@ -1,34 +1,793 @@
-use serde_json::{json, Value};
+use crate::crypto::{decrypt, derive_passphrase_key, encrypt, new_passphrase_kdf};
+use crate::utils::write_secret_file;
+use base64::{engine::general_purpose, Engine as _};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::env;
 use std::fs;
-use std::path::Path;
-
-pub fn get_or_create_config() -> Result<Value, Box<dyn std::error::Error>> {
-    let config_path = Path::new("dotenvpull_config.json");
-    if config_path.exists() {
-        let config_str = fs::read_to_string(config_path)?;
-        Ok(serde_json::from_str(&config_str)?)
-    } else {
-        let config = json!({
-            "api_url": "http://localhost:8080"
-        });
-        fs::write(config_path, serde_json::to_string_pretty(&config)?)?;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The environment used when `--env` isn't given.
+pub const DEFAULT_ENVIRONMENT: &str = "default";
+
+/// The server used when a project or command doesn't name one.
+pub const DEFAULT_SERVER: &str = "default";
+
+const DEFAULT_API_URL: &str = "http://localhost:8080";
+
+/// The name of a project-local config, looked for in the working directory and its parents.
+pub const CONFIG_FILE_NAME: &str = "dotenvpull_config.json";
+
+/// Names a config file to use instead of discovering one.
+pub const CONFIG_ENV: &str = "DOTENVPULL_CONFIG";
+
+/// The layout `save_config` writes. Files without a `version` use the flat layout from
+/// before it, with projects next to `api_url`, and are migrated when read.
+pub const CONFIG_VERSION: u64 = 2;
+
+/// Project fields holding key material, which are encrypted at rest once the config is locked.
+const SECRET_FIELDS: &[&str] = &[
+    "access_key",
+    "encryption_key",
+    "pending_encryption_key",
+    "pending_access_key",
+];
+/// Server profile fields holding credentials, encrypted at rest like the project keys.
+const SERVER_SECRET_FIELDS: &[&str] = &["token"];
+const LOCKED_PREFIX: &str = "locked$";
+/// Value encrypted with the passphrase key to tell whether a passphrase is right.
+const LOCK_CHECK: &str = "dotenvpull";
+
+/// How long the passphrase key is cached after unlocking, unless `lock` is given another.
+pub const DEFAULT_CACHE_TIMEOUT: u64 = 15 * 60;
+
+/// The passphrase key for the rest of this process, with the KDF string it belongs to.
+static UNLOCKED: Mutex<Option<(String, [u8; 32])>> = Mutex::new(None);
+
+/// The config file given with `--config`, which wins over everything else.
+static CONFIG_OVERRIDE: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// The contents of a config file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub version: u64,
+    #[serde(default)]
+    pub servers: BTreeMap<String, Server>,
+    #[serde(default)]
+    pub defaults: Defaults,
+    /// Set once the key material is encrypted with a master passphrase.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lock: Option<Lock>,
+    #[serde(default)]
+    pub projects: BTreeMap<String, Project>,
+}
+
+/// A server profile, selected with `--profile`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Server {
+    pub api_url: String,
+    /// Sent as a bearer token in the `Authorization` header of every request to the server.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Defaults {
+    #[serde(default = "default_server")]
+    pub server: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Lock {
+    /// The Argon2id parameters and salt the passphrase key is derived with.
+    pub kdf: String,
+    /// `LOCK_CHECK` encrypted with the passphrase key.
+    pub check: String,
+    /// Seconds the passphrase key stays cached after unlocking.
+    #[serde(default = "default_cache_timeout")]
+    pub cache_timeout: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Project {
+    /// The server profile the project lives on; the default server if unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server: Option<String>,
+    pub access_key: String,
+    /// Base64 of the 32-byte key the project's content is encrypted with.
+    pub encryption_key: String,
+    /// The key an interrupted `rotate-key` was switching to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pending_encryption_key: Option<String>,
+    /// The access key an interrupted `rotate-key --access-key` was moving the project to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pending_access_key: Option<String>,
+    /// The server revision each environment was last synced at, for `If-Match` on update.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub revisions: BTreeMap<String, u64>,
+}
+
+fn default_server() -> String {
+    DEFAULT_SERVER.to_string()
+}
+
+fn default_cache_timeout() -> u64 {
+    DEFAULT_CACHE_TIMEOUT
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            version: CONFIG_VERSION,
+            servers: BTreeMap::from([(
+                DEFAULT_SERVER.to_string(),
+                Server {
+                    api_url: DEFAULT_API_URL.to_string(),
+                    token: None,
+                },
+            )]),
+            defaults: Defaults::default(),
+            lock: None,
+            projects: BTreeMap::new(),
+        }
+    }
+}
+
+impl Default for Defaults {
+    fn default() -> Self {
+        Defaults {
+            server: default_server(),
+        }
+    }
+}
+
+impl Config {
+    /// Reads a config in the current or the flat layout. A locked config is unlocked with
+    /// the master passphrase.
+    pub fn parse(text: &str) -> Result<Config, String> {
+        Self::parse_with(text, true)
+    }
+
+    /// Reads a config like `parse`, but leaves the key material of a locked config encrypted.
+    pub fn parse_locked(text: &str) -> Result<Config, String> {
+        Self::parse_with(text, false)
+    }
+
+    fn parse_with(text: &str, unlock: bool) -> Result<Config, String> {
+        let value: Value = serde_json::from_str(text).map_err(|e| e.to_string())?;
+        let mut value = migrate(value)?;
+
+        if unlock && !value["lock"].is_null() {
+            let lock: Lock = serde_json::from_value(value["lock"].clone())
+                .map_err(|e| format!("lock: {}", e))?;
+            let key = unlock_key(&lock).map_err(|e| e.to_string())?;
+            map_secret_fields(&mut value, |field| {
+                match field.strip_prefix(LOCKED_PREFIX) {
+                    Some(locked) => decrypt(locked, &key).map_err(|e| e.to_string()),
+                    None => Ok(field.to_string()),
+                }
+            })?;
+        }
+
+        // Check projects one by one first, so an error names the project at fault.
+        if let Some(projects) = value["projects"].as_object() {
+            for (name, project) in projects {
+                Project::deserialize(project).map_err(|e| format!("project '{}': {}", name, e))?;
+            }
+        }
+        let config: Config = serde_json::from_value(value).map_err(|e| e.to_string())?;
+        // A share of a single project comes without servers; the recipient picks the server.
+        if !config.servers.is_empty() {
+            if !config.servers.contains_key(&config.defaults.server) {
+                return Err(format!(
+                    "the default server '{}' is not among the servers",
+                    config.defaults.server
+                ));
+            }
+            for (name, project) in &config.projects {
+                if let Some(server) = project.server.as_ref() {
+                    if !config.servers.contains_key(server) {
+                        return Err(format!(
+                            "project '{}': the server '{}' is not among the servers",
+                            name, server
+                        ));
+                    }
+                }
+            }
+        }
         Ok(config)
     }
+
+    /// The URL of the default server.
+    pub fn api_url(&self) -> &str {
+        self.servers
+            .get(&self.defaults.server)
+            .map_or(DEFAULT_API_URL, |server| server.api_url.as_str())
+    }
+
+    /// The URL of a server profile.
+    pub fn server_url(&self, name: &str) -> Result<&str, String> {
+        self.servers
+            .get(name)
+            .map(|server| server.api_url.as_str())
+            .ok_or_else(|| format!("Profile '{}' not found in config", name))
+    }
+
+    /// The name of the server a project lives on.
+    pub fn project_server<'a>(&'a self, project: &'a Project) -> &'a str {
+        project.server.as_deref().unwrap_or(&self.defaults.server)
+    }
+
+    /// The URL a command should talk to: the server `project_name` lives on if it is in the
+    /// config, otherwise the `profile` given or the default server.
+    pub fn resolve_api_url(
+        &self,
+        project_name: Option<&str>,
+        profile: Option<&str>,
+    ) -> Result<&str, String> {
+        if let Some(profile) = profile {
+            self.server_url(profile)?;
+        }
+        if let Some((name, project)) =
+            project_name.and_then(|name| self.projects.get_key_value(name))
+        {
+            let server = self.project_server(project);
+            if let Some(profile) = profile.filter(|profile| *profile != server) {
+                return Err(format!(
+                    "Project '{}' lives on profile '{}', not '{}'",
+                    name, server, profile
+                ));
+            }
+            return Ok(self.server_url(server).unwrap_or(DEFAULT_API_URL));
+        }
+        match profile {
+            Some(profile) => self.server_url(profile),
+            None => Ok(self.api_url()),
+        }
+    }
+
+    /// The name of the server profile at `api_url`, adding one named after its host if
+    /// there is none yet.
+    pub fn server_for_url(&mut self, api_url: &str) -> String {
+        let api_url = api_url.trim_end_matches('/');
+        if self
+            .servers
+            .get(&self.defaults.server)
+            .is_some_and(|s| s.api_url == api_url)
+        {
+            return self.defaults.server.clone();
+        }
+        if let Some((name, _)) = self.servers.iter().find(|(_, s)| s.api_url == api_url) {
+            return name.clone();
+        }
+        let host = api_url.split_once("://").map_or(api_url, |(_, host)| host);
+        let mut name = host.to_string();
+        let mut n = 1;
+        while self.servers.contains_key(&name) {
+            n += 1;
+            name = format!("{}-{}", host, n);
+        }
+        self.servers.insert(
+            name.clone(),
+            Server {
+                api_url: api_url.to_string(),
+                token: None,
+            },
+        );
+        name
+    }
+
+    pub fn project(&self, name: &str) -> Result<&Project, String> {
+        self.projects
+            .get(name)
+            .ok_or_else(|| format!("Project '{}' not found in config", name))
+    }
+
+    pub fn project_mut(&mut self, name: &str) -> Result<&mut Project, String> {
+        self.projects
+            .get_mut(name)
+            .ok_or_else(|| format!("Project '{}' not found in config", name))
+    }
+}
+
+impl Project {
+    pub fn new(access_key: &str, encryption_key: &str) -> Self {
+        Project {
+            server: None,
+            access_key: access_key.to_string(),
+            encryption_key: encryption_key.to_string(),
+            pending_encryption_key: None,
+            pending_access_key: None,
+            revisions: BTreeMap::new(),
+        }
+    }
+
+    /// The decoded encryption key.
+    pub fn key(&self) -> Result<[u8; 32], String> {
+        general_purpose::STANDARD
+            .decode(&self.encryption_key)
+            .ok()
+            .and_then(|key| key.try_into().ok())
+            .ok_or_else(|| "The project's encryption key is not a valid 32-byte key".to_string())
+    }
+
+    /// The server revision an environment was last synced at.
+    pub fn revision(&self, environment: &str) -> Option<u64> {
+        self.revisions.get(environment).copied()
+    }
+}
+
+/// Brings a config of any known version to the current layout.
+fn migrate(mut value: Value) -> Result<Value, String> {
+    let object = value.as_object_mut().ok_or("expected a JSON object")?;
+    // In the flat layout, `version` can only be the name of a project.
+    if let Some(Value::Number(version)) = object.get("version") {
+        return match version.as_u64() {
+            Some(CONFIG_VERSION) => Ok(value),
+            Some(version) if version > CONFIG_VERSION => Err(format!(
+                "it was written by a newer version of dotenvpull (config version {})",
+                version
+            )),
+            _ => Err("unsupported config version".to_string()),
+        };
+    }
+
+    // The flat layout: `api_url`, the passphrase lock, and projects by name.
+    let mut config = serde_json::Map::new();
+    let mut projects = serde_json::Map::new();
+    let mut servers = serde_json::Map::new();
+    for (name, entry) in std::mem::take(object) {
+        match name.as_str() {
+            "api_url" if entry.is_string() => {
+                servers.insert(
+                    DEFAULT_SERVER.to_string(),
+                    serde_json::json!({ "api_url": entry }),
+                );
+            }
+            "lock" if entry.get("kdf").is_some() => {
+                config.insert(name, entry);
+            }
+            _ => {
+                let mut entry = entry;
+                if let Some(project) = entry.as_object_mut() {
+                    // Before environments, one revision covered the whole project.
+                    if let Some(revision) = project.remove("revision") {
+                        let revisions = project
+                            .entry("revisions")
+                            .or_insert_with(|| serde_json::json!({}));
+                        if revisions.get(DEFAULT_ENVIRONMENT).is_none() {
+                            revisions[DEFAULT_ENVIRONMENT] = revision;
+                        }
+                    }
+                    if let Some(revisions) =
+                        project.get_mut("revisions").and_then(Value::as_object_mut)
+                    {
+                        revisions.retain(|_, revision| !revision.is_null());
+                    }
+                }
+                projects.insert(name, entry);
+            }
+        }
+    }
+    if servers.is_empty() {
+        servers.insert(
+            DEFAULT_SERVER.to_string(),
+            serde_json::json!({ "api_url": DEFAULT_API_URL }),
+        );
+    }
+    config.insert("version".to_string(), CONFIG_VERSION.into());
+    config.insert("servers".to_string(), servers.into());
+    config.insert("projects".to_string(), projects.into());
+    Ok(config.into())
+}
+
+/// Uses `path` as the config file instead of discovering one, or goes back to discovery
+/// with `None`.
+pub fn set_config_path(path: Option<&Path>) {
+    *CONFIG_OVERRIDE.lock().unwrap() = path.map(Path::to_path_buf);
+}
+
+/// The config file to read and write, the first of:
+/// - the file given with `--config` or in `DOTENVPULL_CONFIG`,
+/// - a `dotenvpull_config.json` in the working directory or the nearest parent that has one,
+/// - the global config at `$XDG_CONFIG_HOME/dotenvpull/config` (`~/.config` by default).
+pub fn config_path() -> PathBuf {
+    if let Some(path) = CONFIG_OVERRIDE.lock().unwrap().clone() {
+        return path;
+    }
+    if let Some(path) = env::var_os(CONFIG_ENV).filter(|path| !path.is_empty()) {
+        return PathBuf::from(path);
+    }
+    if let Ok(dir) = env::current_dir() {
+        if let Some(path) = dir
+            .ancestors()
+            .map(|dir| dir.join(CONFIG_FILE_NAME))
+            .find(|path| path.is_file())
+        {
+            return path;
+        }
+    }
+    global_config_path().unwrap_or_else(|| PathBuf::from(CONFIG_FILE_NAME))
+}
+
+fn global_config_path() -> Option<PathBuf> {
+    let dir = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(dir.join("dotenvpull").join("config"))
+}
+
+/// Reads the config, or an empty one if there is no config file yet; nothing is written
+/// until `save_config`. If it is locked, the key material is decrypted with the master
+/// passphrase, which is taken from the key cache, `DOTENVPULL_PASSPHRASE` or a prompt,
+/// in that order.
+pub fn load_config() -> Result<Config, Box<dyn std::error::Error>> {
+    read_config(Config::parse)
+}
+
+/// Reads the config without asking for the passphrase, for commands that don't need the
+/// keys: those of a locked config stay encrypted, and `save_config` writes them back as they
+/// are.
+pub fn load_config_locked() -> Result<Config, Box<dyn std::error::Error>> {
+    read_config(Config::parse_locked)
+}
+
+fn read_config(
+    parse: fn(&str) -> Result<Config, String>,
+) -> Result<Config, Box<dyn std::error::Error>> {
+    let config_path = config_path();
+    match fs::read_to_string(&config_path) {
+        Ok(config_str) => Ok(parse(&config_str)
+            .map_err(|e| format!("{} is not a valid config: {}", config_path.display(), e))?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Config::default()),
+        Err(e) => Err(format!("Could not read {}: {}", config_path.display(), e).into()),
+    }
+}
+
+/// Writes the config back, encrypting its key material again if it is locked. The
+/// passphrase is only needed if some of it isn't encrypted yet.
+pub fn save_config(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let mut value = serde_json::to_value(config)?;
+    if let Some(lock) = &config.lock {
+        let mut key = None;
+        map_secret_fields(&mut value, |field| {
+            if field.starts_with(LOCKED_PREFIX) {
+                return Ok(field.to_string());
+            }
+            let key = match key {
+                Some(key) => key,
+                None => *key.insert(unlock_key(lock).map_err(|e| e.to_string())?),
+            };
+            Ok(format!("{}{}", LOCKED_PREFIX, encrypt(field, &key)))
+        })?;
+    }
+    let config_path = config_path();
+    if let Some(dir) = config_path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+    {
+        fs::create_dir_all(dir)?;
+    }
+    write_secret_file(config_path, serde_json::to_string_pretty(&value)?)?;
+    Ok(())
+}
+
+/// Encrypts the key material in the config with a key derived from `passphrase`. The key is
+/// cached for `cache_timeout` seconds after each unlock, so commands don't prompt every time.
+pub fn lock_config(passphrase: &str, cache_timeout: u64) -> Result<(), Box<dyn std::error::Error>> {
+    let mut config = load_config_locked()?;
+    if config.lock.is_some() {
+        return Err(format!("{} is already locked", config_path().display()).into());
+    }
+    let kdf = new_passphrase_kdf();
+    let key = derive_passphrase_key(&kdf, passphrase)?;
+    config.lock = Some(Lock {
+        check: encrypt(LOCK_CHECK, &key),
+        kdf: kdf.clone(),
+        cache_timeout,
+    });
+    remember_key(&kdf, key, cache_timeout)?;
+    save_config(&config)
+}
+
+/// Decrypts the key material in the config for good, after asking for the passphrase.
+pub fn remove_config_lock() -> Result<(), Box<dyn std::error::Error>> {
+    let mut config = load_config()?;
+    let Some(lock) = config.lock.take() else {
+        return Err(format!("{} is not locked", config_path().display()).into());
+    };
+    if let Some(path) = cache_path(&lock.kdf) {
+        let _ = fs::remove_file(path);
+    }
+    save_config(&config)
+}
+
+/// Drops the cached passphrase key, so the next command asks for the passphrase again.
+pub fn forget_passphrase() -> Result<(), Box<dyn std::error::Error>> {
+    *UNLOCKED.lock().unwrap() = None;
+    let config: Value = match fs::read_to_string(config_path()) {
+        Ok(config) => serde_json::from_str(&config)?,
+        Err(_) => return Ok(()),
+    };
+    if let Some(kdf) = config["lock"]["kdf"].as_str() {
+        if let Some(path) = cache_path(kdf) {
+            let _ = fs::remove_file(path);
+        }
+    }
+    Ok(())
+}
+
+/// Applies `f` to every key-material field of every project, and every server credential.
+fn map_secret_fields(
+    config: &mut Value,
+    mut f: impl FnMut(&str) -> Result<String, String>,
+) -> Result<(), String> {
+    for (section, fields) in [
+        ("projects", SECRET_FIELDS),
+        ("servers", SERVER_SECRET_FIELDS),
+    ] {
+        let Some(entries) = config.get_mut(section).and_then(Value::as_object_mut) else {
+            continue;
+        };
+        for entry in entries.values_mut() {
+            let Some(entry) = entry.as_object_mut() else {
+                continue;
+            };
+            for field in fields {
+                if let Some(Value::String(value)) = entry.get_mut(*field) {
+                    *value = f(value)?;
+                }
+            }
+        }
+    }
+    Ok(())
 }
 
+/// Finds the passphrase key of a locked config: from this process, the key cache, or by
+/// asking for the passphrase.
+fn unlock_key(lock: &Lock) -> Result<[u8; 32], Box<dyn std::error::Error>> {
+    let kdf = lock.kdf.as_str();
+    let is_right = |key: &[u8; 32]| decrypt(&lock.check, key).is_ok_and(|c| c == LOCK_CHECK);
+
+    if let Some((unlocked_kdf, key)) = UNLOCKED.lock().unwrap().as_ref() {
+        if unlocked_kdf == kdf {
+            return Ok(*key);
+        }
+    }
+    if let Some(key) = cached_key(kdf).filter(|key| is_right(key)) {
+        *UNLOCKED.lock().unwrap() = Some((kdf.to_string(), key));
+        return Ok(key);
+    }
+
+    let key = match env::var("DOTENVPULL_PASSPHRASE") {
+        Ok(passphrase) => Some(derive_passphrase_key(kdf, &passphrase)?).filter(is_right),
+        Err(_) => {
+            let mut found = None;
+            for _ in 0..3 {
+                let passphrase = rpassword::prompt_password(format!(
+                    "Passphrase for {}: ",
+                    config_path().display()
+                ))?;
+                found = Some(derive_passphrase_key(kdf, &passphrase)?).filter(is_right);
+                if found.is_some() {
+                    break;
+                }
+                println!("Wrong passphrase, try again.");
+            }
+            found
+        }
+    };
+    let key = key.ok_or_else(|| format!("Wrong passphrase for {}", config_path().display()))?;
+    remember_key(kdf, key, lock.cache_timeout)?;
+    Ok(key)
+}
+
+/// Keeps the passphrase key for the rest of this process, and for `cache_timeout` seconds
+/// in the runtime directory, in a file only the current user can read.
+fn remember_key(
+    kdf: &str,
+    key: [u8; 32],
+    cache_timeout: u64,
+) -> Result<(), Box<dyn std::error::Error>> {
+    *UNLOCKED.lock().unwrap() = Some((kdf.to_string(), key));
+    let Some(path) = cache_path(kdf).filter(|_| cache_timeout > 0) else {
+        return Ok(());
+    };
+    if let Some(dir) = path.parent() {
+        create_private_dir(dir)?;
+    }
+    purge_expired_keys();
+    write_secret_file(
+        path,
+        format!(
+            "{}\n{}\n",
+            unix_now() + cache_timeout,
+            general_purpose::STANDARD.encode(key)
+        ),
+    )?;
+    Ok(())
+}
+
+/// The cached passphrase key, unless there is none or it has expired.
+fn cached_key(kdf: &str) -> Option<[u8; 32]> {
+    purge_expired_keys();
+    read_cached_key(&cache_path(kdf)?)
+}
+
+/// Reads a cached key file, giving `None` if it can't be read or has expired.
+fn read_cached_key(path: &Path) -> Option<[u8; 32]> {
+    let cached = fs::read_to_string(path).ok()?;
+    let (expires_at, key) = cached.trim().split_once('\n')?;
+    if expires_at.parse::<u64>().ok()? <= unix_now() {
+        return None;
+    }
+    general_purpose::STANDARD.decode(key).ok()?.try_into().ok()
+}
+
+/// Deletes every cached key that has expired or can't be read, whichever config it is for.
+fn purge_expired_keys() {
+    let Some(entries) = cache_dir().and_then(|dir| fs::read_dir(dir).ok()) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        if read_cached_key(&entry.path()).is_none() {
+            let _ = fs::remove_file(entry.path());
+        }
+    }
+}
+
+/// Where passphrase keys are cached: the per-user runtime directory, which lives in memory
+/// and is cleared on logout. Without one, keys are only kept for the current process, so
+/// they never reach the disk.
+fn cache_dir() -> Option<PathBuf> {
+    env::var_os("XDG_RUNTIME_DIR")
+        .filter(|dir| !dir.is_empty())
+        .map(|dir| PathBuf::from(dir).join("dotenvpull"))
+}
+
+/// Where the key of a locked config is cached.
+fn cache_path(kdf: &str) -> Option<PathBuf> {
+    let id = Sha256::digest(kdf.as_bytes());
+    Some(cache_dir()?.join(format!(
+        "{}.key",
+        id[..8]
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>()
+    )))
+}
+
+/// Creates `dir` if needed, and makes sure only the current user can open it.
+fn create_private_dir(dir: &Path) -> std::io::Result<()> {
+    fs::create_dir_all(dir)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let permissions = fs::metadata(dir)?.permissions();
+        if permissions.mode() & 0o077 != 0 {
+            fs::set_permissions(dir, fs::Permissions::from_mode(0o700))?;
+        }
+    }
+    Ok(())
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Adds a project living on the server at `api_url`.
 pub fn update_config(
     project_name: &str,
+    api_url: &str,
     access_key: &str,
     encryption_key: &str,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let mut config = get_or_create_config()?;
-    config[project_name] = json!({
-        "access_key": access_key,
-        "encryption_key": encryption_key
-    });
-    fs::write(
-        "dotenvpull_config.json",
-        serde_json::to_string_pretty(&config)?,
-    )?;
-    Ok(())
+    let mut config = load_config()?;
+    let server = config.server_for_url(api_url);
+    config.projects.insert(
+        project_name.to_string(),
+        Project {
+            server: Some(server),
+            ..Project::new(access_key, encryption_key)
+        },
+    );
+    save_config(&config)
+}
+
+/// Adds a server profile, or replaces the URL and credentials of an existing one.
+pub fn set_server(
+    name: &str,
+    api_url: &str,
+    token: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut config = load_config_locked()?;
+    config.servers.insert(
+        name.to_string(),
+        Server {
+            api_url: api_url.trim_end_matches('/').to_string(),
+            token: token.map(String::from),
+        },
+    );
+    save_config(&config)
+}
+
+/// The token of the server profile at `api_url`, if it has one. The config is only unlocked
+/// if the token is locked.
+pub fn server_token(api_url: &str) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let api_url = api_url.trim_end_matches('/');
+    let find = |config: &Config| {
+        config
+            .servers
+            .values()
+            .find(|server| server.api_url == api_url)
+            .and_then(|server| server.token.clone())
+    };
+    match find(&load_config_locked()?) {
+        Some(token) if token.starts_with(LOCKED_PREFIX) => Ok(find(&load_config()?)),
+        token => Ok(token),
+    }
+}
+
+/// Removes a server profile that no project lives on and that isn't the default.
+pub fn remove_server(name: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let mut config = load_config_locked()?;
+    config.server_url(name)?;
+    if config.defaults.server == name {
+        return Err(format!("Profile '{}' is the default profile", name).into());
+    }
+    let projects: Vec<&str> = config
+        .projects
+        .iter()
+        .filter(|(_, project)| config.project_server(project) == name)
+        .map(|(project_name, _)| project_name.as_str())
+        .collect();
+    if !projects.is_empty() {
+        return Err(format!("Profile '{}' is used by {}", name, projects.join(", ")).into());
+    }
+    config.servers.remove(name);
+    save_config(&config)
+}
+
+/// Makes a server profile the one used when neither `--profile` nor a project names one.
+pub fn set_default_server(name: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let mut config = load_config_locked()?;
+    config.server_url(name)?;
+    // Projects from before profiles live on the default server, so they stay where they are.
+    let previous = config.defaults.server.clone();
+    for project in config.projects.values_mut() {
+        project.server.get_or_insert_with(|| previous.clone());
+    }
+    config.defaults.server = name.to_string();
+    save_config(&config)
+}
+
+/// Remembers the server revision an environment was last synced at, for `If-Match` on update.
+/// `None` forgets it, e.g. once the environment is deleted.
+pub fn set_project_revision(
+    project_name: &str,
+    environment: &str,
+    revision: Option<u64>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut config = load_config_locked()?;
+    let Some(project) = config.projects.get_mut(project_name) else {
+        return Ok(());
+    };
+    match revision {
+        Some(revision) => project.revisions.insert(environment.to_string(), revision),
+        None => project.revisions.remove(environment),
+    };
+    save_config(&config)
 }
@@ -1,25 +1,330 @@
 use aes_gcm::{
-    aead::{Aead, KeyInit},
+    aead::{Aead, KeyInit, Payload},
     Aes256Gcm, Key, Nonce,
 };
+use argon2::{Algorithm, Argon2, Params, Version};
 use base64::{engine::general_purpose, Engine as _};
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression};
 use rand::Rng;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::io::{Read, Write};
+
+const PASSPHRASE_PREFIX: &str = "argon2id$";
+/// Upper bounds on the Argon2 parameters read from a KDF string, which may come from the
+/// server: a few times the defaults every KDF is written with.
+const MAX_M_COST: u32 = 4 * Params::DEFAULT_M_COST;
+const MAX_T_COST: u32 = 4 * Params::DEFAULT_T_COST;
+const MAX_P_COST: u32 = 4 * Params::DEFAULT_P_COST;
+
+/// Encrypted data is an envelope: a header, the nonce, then the AES-GCM ciphertext, all
+/// base64 encoded. The header is authenticated as associated data, so none of it can be
+/// altered without decryption failing. Data from before the envelope existed is a bare
+/// `nonce || ciphertext` and is still accepted.
+///
+/// The header is `MAGIC`, the format version, the algorithm, the flags and the key ID.
+const MAGIC: &[u8; 3] = b"DEP";
+const ENVELOPE_VERSION: u8 = 1;
+const ALGORITHM_AES_256_GCM: u8 = 1;
+const FLAG_COMPRESSED: u8 = 0b1;
+const KEY_ID_LEN: usize = 8;
+const HEADER_LEN: usize = MAGIC.len() + 3 + KEY_ID_LEN;
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+/// Content shorter than this is not worth compressing.
+const COMPRESSION_THRESHOLD: usize = 256;
+
+/// Plaintexts start with this byte and a content-type byte. Payloads from before content
+/// types existed are bare UTF-8 text, which never starts with a NUL byte.
+const CONTENT_MARKER: u8 = 0;
+
+/// Why encrypted data could not be decrypted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CryptoError {
+    /// The data is not valid base64, or not in a format this version understands.
+    BadEncoding,
+    /// The data is too short to hold a nonce and an authentication tag.
+    Truncated,
+    /// The data was written with a newer format version or algorithm.
+    UnsupportedFormat,
+    /// The data was encrypted with a different key.
+    KeyMismatch,
+    /// The key is wrong, or the data was altered.
+    AuthenticationFailed,
+    /// The data decrypted fine but is not UTF-8 text.
+    InvalidUtf8,
+    /// The passphrase KDF asks for more memory or time than dotenvpull ever uses.
+    KdfTooCostly,
+}
+
+impl fmt::Display for CryptoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = match self {
+            CryptoError::BadEncoding => "the encrypted data is not in a recognised format",
+            CryptoError::Truncated => "the encrypted data is truncated",
+            CryptoError::UnsupportedFormat => {
+                "the data was encrypted by a newer version of dotenvpull; please upgrade"
+            }
+            CryptoError::KeyMismatch => "the data was encrypted with a different key",
+            CryptoError::AuthenticationFailed => {
+                "the data could not be decrypted; the key is wrong or the data was tampered with"
+            }
+            CryptoError::InvalidUtf8 => "the decrypted data is not valid text",
+            CryptoError::KdfTooCostly => {
+                "the passphrase key derivation asks for more memory or time than allowed"
+            }
+        };
+        f.write_str(message)
+    }
+}
+
+impl std::error::Error for CryptoError {}
+
+/// What an encrypted payload holds, so binary files come back byte for byte.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentType {
+    Text,
+    Binary,
+}
+
+impl ContentType {
+    /// `Text` for valid UTF-8, `Binary` for anything else.
+    pub fn detect(data: &[u8]) -> Self {
+        match std::str::from_utf8(data) {
+            Ok(_) => ContentType::Text,
+            Err(_) => ContentType::Binary,
+        }
+    }
+
+    fn to_byte(self) -> u8 {
+        match self {
+            ContentType::Text => b't',
+            ContentType::Binary => b'b',
+        }
+    }
+
+    fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            b't' => Some(ContentType::Text),
+            b'b' => Some(ContentType::Binary),
+            _ => None,
+        }
+    }
+}
 
 pub fn encrypt(data: &str, key: &[u8; 32]) -> String {
+    encrypt_bytes(data.as_bytes(), ContentType::Text, key)
+}
+
+/// Encrypts arbitrary bytes, recording their content type alongside them. Larger content
+/// is compressed first when that makes it smaller.
+pub fn encrypt_bytes(data: &[u8], content_type: ContentType, key: &[u8; 32]) -> String {
+    let mut plaintext = Vec::with_capacity(data.len() + 2);
+    plaintext.push(CONTENT_MARKER);
+    plaintext.push(content_type.to_byte());
+    plaintext.extend_from_slice(data);
+
+    let mut flags = 0;
+    if plaintext.len() >= COMPRESSION_THRESHOLD {
+        let compressed = compress(&plaintext);
+        if compressed.len() < plaintext.len() {
+            plaintext = compressed;
+            flags |= FLAG_COMPRESSED;
+        }
+    }
+
+    let mut envelope = Vec::with_capacity(HEADER_LEN + NONCE_LEN + plaintext.len() + TAG_LEN);
+    envelope.extend_from_slice(MAGIC);
+    envelope.extend_from_slice(&[ENVELOPE_VERSION, ALGORITHM_AES_256_GCM, flags]);
+    envelope.extend_from_slice(&key_id(key));
+
     let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
-    let nonce_bytes: [u8; 12] = rand::thread_rng().gen();
+    let nonce_bytes: [u8; NONCE_LEN] = rand::thread_rng().gen();
     let nonce = Nonce::from_slice(&nonce_bytes);
-    let ciphertext = cipher.encrypt(nonce, data.as_bytes()).unwrap();
-    let mut result = nonce.to_vec();
-    result.extend_from_slice(&ciphertext);
-    general_purpose::STANDARD.encode(&result)
+    let payload = Payload {
+        msg: &plaintext,
+        aad: &envelope,
+    };
+    let ciphertext = cipher.encrypt(nonce, payload).unwrap();
+    envelope.extend_from_slice(&nonce_bytes);
+    envelope.extend_from_slice(&ciphertext);
+    general_purpose::STANDARD.encode(&envelope)
+}
+
+/// Decrypts text encrypted with `encrypt`.
+pub fn decrypt(encrypted_data: &str, key: &[u8; 32]) -> Result<String, CryptoError> {
+    match decrypt_bytes(encrypted_data, key)? {
+        (ContentType::Text, data) => String::from_utf8(data).map_err(|_| CryptoError::InvalidUtf8),
+        (ContentType::Binary, _) => Err(CryptoError::InvalidUtf8),
+    }
+}
+
+/// Decrypts data encrypted with `encrypt_bytes`, along with its content type.
+pub fn decrypt_bytes(
+    encrypted_data: &str,
+    key: &[u8; 32],
+) -> Result<(ContentType, Vec<u8>), CryptoError> {
+    let encrypted_bytes = general_purpose::STANDARD
+        .decode(encrypted_data)
+        .map_err(|_| CryptoError::BadEncoding)?;
+    let mut plaintext = if encrypted_bytes.starts_with(MAGIC) {
+        // A legacy nonce can start with the magic bytes by chance, so fall back to the
+        // legacy form before giving up.
+        open_envelope(&encrypted_bytes, key)
+            .or_else(|e| open_legacy(&encrypted_bytes, key).map_err(|_| e))?
+    } else {
+        open_legacy(&encrypted_bytes, key)?
+    };
+    match plaintext.as_slice() {
+        [CONTENT_MARKER, content_type, ..] => {
+            let content_type =
+                ContentType::from_byte(*content_type).ok_or(CryptoError::BadEncoding)?;
+            plaintext.drain(..2);
+            Ok((content_type, plaintext))
+        }
+        _ => Ok((ContentType::Text, plaintext)),
+    }
 }
 
-pub fn decrypt(encrypted_data: &str, key: &[u8; 32]) -> String {
-    let encrypted_bytes = general_purpose::STANDARD.decode(encrypted_data).unwrap();
-    let nonce = Nonce::from_slice(&encrypted_bytes[..12]);
-    let ciphertext = &encrypted_bytes[12..];
+fn open_envelope(envelope: &[u8], key: &[u8; 32]) -> Result<Vec<u8>, CryptoError> {
+    if envelope.len() < HEADER_LEN + NONCE_LEN + TAG_LEN {
+        return Err(CryptoError::Truncated);
+    }
+    let (header, rest) = envelope.split_at(HEADER_LEN);
+    let (version, algorithm, flags) = (header[3], header[4], header[5]);
+    if version != ENVELOPE_VERSION || algorithm != ALGORITHM_AES_256_GCM {
+        return Err(CryptoError::UnsupportedFormat);
+    }
+    if flags & !FLAG_COMPRESSED != 0 {
+        return Err(CryptoError::UnsupportedFormat);
+    }
+    if header[6..] != key_id(key) {
+        return Err(CryptoError::KeyMismatch);
+    }
+
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+    let payload = Payload {
+        msg: ciphertext,
+        aad: header,
+    };
+    let plaintext = cipher
+        .decrypt(Nonce::from_slice(nonce), payload)
+        .map_err(|_| CryptoError::AuthenticationFailed)?;
+    if flags & FLAG_COMPRESSED != 0 {
+        decompress(&plaintext)
+    } else {
+        Ok(plaintext)
+    }
+}
+
+/// Decrypts the headerless `nonce || ciphertext` form written before the envelope existed.
+fn open_legacy(encrypted: &[u8], key: &[u8; 32]) -> Result<Vec<u8>, CryptoError> {
+    if encrypted.len() < NONCE_LEN + TAG_LEN {
+        return Err(CryptoError::Truncated);
+    }
+    let (nonce, ciphertext) = encrypted.split_at(NONCE_LEN);
     let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
-    let plaintext = cipher.decrypt(nonce, ciphertext).unwrap();
-    String::from_utf8(plaintext).unwrap()
+    cipher
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| CryptoError::AuthenticationFailed)
+}
+
+/// Identifies a key without revealing it: the start of its SHA-256 hash.
+fn key_id(key: &[u8; 32]) -> [u8; KEY_ID_LEN] {
+    let hash = Sha256::digest(key);
+    let mut id = [0u8; KEY_ID_LEN];
+    id.copy_from_slice(&hash[..KEY_ID_LEN]);
+    id
+}
+
+fn compress(data: &[u8]) -> Vec<u8> {
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+fn decompress(data: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    let mut decompressed = Vec::new();
+    DeflateDecoder::new(data)
+        .read_to_end(&mut decompressed)
+        .map_err(|_| CryptoError::BadEncoding)?;
+    Ok(decompressed)
+}
+
+/// Encrypts `data` again with a key derived from `passphrase` using Argon2id.
+///
+/// The result is `argon2id$m=<kib>,t=<passes>,p=<lanes>$<salt>$<ciphertext>`, so the
+/// KDF parameters travel with the data.
+pub fn wrap_with_passphrase(data: &str, passphrase: &str) -> String {
+    let kdf = new_passphrase_kdf();
+    let key = derive_passphrase_key(&kdf, passphrase).expect("Default Argon2 params are valid");
+    format!("{}${}", kdf, encrypt(data, &key))
+}
+
+pub fn is_passphrase_wrapped(data: &str) -> bool {
+    data.starts_with(PASSPHRASE_PREFIX)
+}
+
+/// Reverses `wrap_with_passphrase`. A wrong passphrase gives `KeyMismatch` or
+/// `AuthenticationFailed`.
+pub fn unwrap_with_passphrase(wrapped: &str, passphrase: &str) -> Result<String, CryptoError> {
+    let (kdf, data) = wrapped.rsplit_once('$').ok_or(CryptoError::BadEncoding)?;
+    let key = derive_passphrase_key(kdf, passphrase)?;
+    decrypt(data, &key)
+}
+
+/// Describes a fresh passphrase-derived key: `argon2id$m=<kib>,t=<passes>,p=<lanes>$<salt>`
+/// with the default Argon2id parameters and a random salt.
+pub fn new_passphrase_kdf() -> String {
+    let params = Params::default();
+    let salt: [u8; 16] = rand::thread_rng().gen();
+    format!(
+        "{}m={},t={},p={}${}",
+        PASSPHRASE_PREFIX,
+        params.m_cost(),
+        params.t_cost(),
+        params.p_cost(),
+        general_purpose::STANDARD.encode(salt),
+    )
+}
+
+/// Derives the key described by a `new_passphrase_kdf` string from `passphrase`. A malformed
+/// description gives `BadEncoding`, and one costlier than the defaults allow `KdfTooCostly`.
+pub fn derive_passphrase_key(kdf: &str, passphrase: &str) -> Result<[u8; 32], CryptoError> {
+    let parse = || -> Option<(u32, u32, u32, Vec<u8>)> {
+        let mut parts = kdf.strip_prefix(PASSPHRASE_PREFIX)?.split('$');
+        let (settings, salt) = (parts.next()?, parts.next()?);
+        if parts.next().is_some() {
+            return None;
+        }
+
+        let (mut m_cost, mut t_cost, mut p_cost) = (None, None, None);
+        for setting in settings.split(',') {
+            let (name, value) = setting.split_once('=')?;
+            let value = value.parse().ok()?;
+            match name {
+                "m" => m_cost = Some(value),
+                "t" => t_cost = Some(value),
+                "p" => p_cost = Some(value),
+                _ => return None,
+            }
+        }
+        let salt = general_purpose::STANDARD.decode(salt).ok()?;
+        Some((m_cost?, t_cost?, p_cost?, salt))
+    };
+    let (m_cost, t_cost, p_cost, salt) = parse().ok_or(CryptoError::BadEncoding)?;
+    if m_cost > MAX_M_COST || t_cost > MAX_T_COST || p_cost > MAX_P_COST {
+        return Err(CryptoError::KdfTooCostly);
+    }
+    let params = Params::new(m_cost, t_cost, p_cost, None).map_err(|_| CryptoError::BadEncoding)?;
+    derive_key(passphrase, &salt, params).ok_or(CryptoError::BadEncoding)
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], params: Params) -> Option<[u8; 32]> {
+    let mut key = [0u8; 32];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .ok()?;
+    Some(key)
 }
@@ -0,0 +1,108 @@
+use crate::dotenv::DotEnv;
+
+/// Lines of unchanged context shown around each change in a line diff.
+const CONTEXT: usize = 2;
+const MASK: &str = "****";
+
+/// Describes how `local` differs from `remote`, or `None` if they are the same.
+///
+/// When both sides parse as dotenv files the result lists added, removed and changed
+/// keys, with values masked unless `show_values` is set. Anything else gets a line diff.
+pub fn render(remote: &str, local: &str, show_values: bool) -> Option<String> {
+    if remote == local {
+        return None;
+    }
+    match (DotEnv::parse(remote), DotEnv::parse(local)) {
+        (Ok(remote), Ok(local)) => Some(key_diff(&remote, &local, show_values)),
+        _ => Some(line_diff(remote, local)),
+    }
+}
+
+fn key_diff(remote: &DotEnv, local: &DotEnv, show_values: bool) -> String {
+    let remote = remote.vars();
+    let local = local.vars();
+    let shown = |value: &str| {
+        if show_values {
+            value.to_string()
+        } else {
+            MASK.to_string()
+        }
+    };
+
+    let mut out = Vec::new();
+    for (key, old) in &remote {
+        match local.iter().find(|(k, _)| k == key) {
+            None => out.push(format!("- {}={}", key, shown(old))),
+            Some((_, new)) if new != old => {
+                out.push(format!("~ {}: {} -> {}", key, shown(old), shown(new)))
+            }
+            Some(_) => {}
+        }
+    }
+    for (key, new) in &local {
+        if !remote.iter().any(|(k, _)| k == key) {
+            out.push(format!("+ {}={}", key, shown(new)));
+        }
+    }
+
+    if out.is_empty() {
+        return "Variables are identical; only comments or formatting differ.".to_string();
+    }
+    out.join("\n")
+}
+
+fn line_diff(remote: &str, local: &str) -> String {
+    let old: Vec<&str> = remote.lines().collect();
+    let new: Vec<&str> = local.lines().collect();
+
+    // Longest common subsequence table, filled from the end.
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            lines.push((' ', old[i]));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            lines.push(('-', old[i]));
+            i += 1;
+        } else {
+            lines.push(('+', new[j]));
+            j += 1;
+        }
+    }
+
+    let near_change = |index: usize| {
+        let from = index.saturating_sub(CONTEXT);
+        let to = (index + CONTEXT + 1).min(lines.len());
+        lines[from..to].iter().any(|(tag, _)| *tag != ' ')
+    };
+    let mut out = Vec::new();
+    let mut skipped = false;
+    for (index, (tag, line)) in lines.iter().enumerate() {
+        if near_change(index) {
+            if skipped {
+                out.push("...".to_string());
+                skipped = false;
+            }
+            out.push(format!("{} {}", tag, line));
+        } else {
+            skipped = true;
+        }
+    }
+    if out.is_empty() {
+        return "Contents differ only in line endings.".to_string();
+    }
+    out.join("\n")
+}
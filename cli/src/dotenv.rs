@@ -0,0 +1,290 @@
+use std::fmt;
+
+/// A parsed dotenv file.
+///
+/// Comments, blank lines and untouched entries keep their original text, so a file
+/// can be edited with `set`/`unset` and written back without reformatting it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DotEnv {
+    lines: Vec<Line>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Line {
+    /// A comment or blank line, kept verbatim.
+    Other(String),
+    Entry(Entry),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Entry {
+    key: String,
+    value: String,
+    exported: bool,
+    /// The source text of the entry; `None` once it has been changed.
+    raw: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl DotEnv {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses dotenv content: `KEY=value` pairs with optional `export` prefixes,
+    /// `#` comments, and single- or double-quoted values that may span several lines.
+    pub fn parse(input: &str) -> Result<Self, ParseError> {
+        let mut lines = Vec::new();
+        let mut rest = input;
+        let mut line_no = 1;
+
+        while !rest.is_empty() {
+            let line_end = rest.find('\n').map_or(rest.len(), |i| i + 1);
+            let line = &rest[..line_end];
+            let trimmed = line.trim();
+
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                lines.push(Line::Other(line.trim_end_matches(['\n', '\r']).to_string()));
+                rest = &rest[line_end..];
+                line_no += 1;
+                continue;
+            }
+
+            let (entry, consumed) = parse_entry(rest, line_no)?;
+            let raw = &rest[..consumed];
+            line_no += raw.matches('\n').count();
+            lines.push(Line::Entry(Entry {
+                raw: Some(raw.trim_end_matches(['\n', '\r']).to_string()),
+                ..entry
+            }));
+            rest = &rest[consumed..];
+        }
+
+        Ok(DotEnv { lines })
+    }
+
+    /// Returns the value of `key`; if it is defined more than once, the last one wins.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries()
+            .filter(|e| e.key == key)
+            .last()
+            .map(|e| e.value.as_str())
+    }
+
+    /// Sets `key`, replacing every existing definition or appending a new one.
+    pub fn set(&mut self, key: &str, value: &str) {
+        let mut found = false;
+        self.lines.retain_mut(|line| match line {
+            Line::Entry(entry) if entry.key == key => {
+                if found {
+                    return false;
+                }
+                found = true;
+                entry.value = value.to_string();
+                entry.raw = None;
+                true
+            }
+            _ => true,
+        });
+        if !found {
+            self.lines.push(Line::Entry(Entry {
+                key: key.to_string(),
+                value: value.to_string(),
+                exported: false,
+                raw: None,
+            }));
+        }
+    }
+
+    /// Removes every definition of `key`, returning whether there was one.
+    pub fn unset(&mut self, key: &str) -> bool {
+        let before = self.lines.len();
+        self.lines
+            .retain(|line| !matches!(line, Line::Entry(entry) if entry.key == key));
+        self.lines.len() != before
+    }
+
+    /// Lays `other` over this file: its variables replace ones defined here, and ones
+    /// only it defines are appended.
+    pub fn overlay(&mut self, other: &DotEnv) {
+        for (key, value) in other.vars() {
+            self.set(&key, &value);
+        }
+    }
+
+    /// The variables in order of first definition, with later definitions winning.
+    pub fn vars(&self) -> Vec<(String, String)> {
+        let mut vars: Vec<(String, String)> = Vec::new();
+        for entry in self.entries() {
+            match vars.iter_mut().find(|(key, _)| *key == entry.key) {
+                Some((_, value)) => *value = entry.value.clone(),
+                None => vars.push((entry.key.clone(), entry.value.clone())),
+            }
+        }
+        vars
+    }
+
+    fn entries(&self) -> impl Iterator<Item = &Entry> {
+        self.lines.iter().filter_map(|line| match line {
+            Line::Entry(entry) => Some(entry),
+            Line::Other(_) => None,
+        })
+    }
+}
+
+impl fmt::Display for DotEnv {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for line in &self.lines {
+            match line {
+                Line::Other(text) => writeln!(f, "{}", text)?,
+                Line::Entry(Entry { raw: Some(raw), .. }) => writeln!(f, "{}", raw)?,
+                Line::Entry(entry) => {
+                    if entry.exported {
+                        write!(f, "export ")?;
+                    }
+                    writeln!(f, "{}={}", entry.key, quote(&entry.value))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn is_valid_key(key: &str) -> bool {
+    let mut chars = key.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'))
+}
+
+/// Renders a value so that `parse` reads it back unchanged.
+pub fn quote(value: &str) -> String {
+    let bare = value
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || "_-./:@,+%=~^".contains(c));
+    if bare {
+        return value.to_string();
+    }
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '\\' => quoted.push_str("\\\\"),
+            '"' => quoted.push_str("\\\""),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Parses one entry at the start of `input`, returning it and how many bytes it used.
+fn parse_entry(input: &str, line_no: usize) -> Result<(Entry, usize), ParseError> {
+    let error = |message: &str| ParseError {
+        line: line_no,
+        message: message.to_string(),
+    };
+
+    let start = input.len() - input.trim_start_matches([' ', '\t']).len();
+    let mut pos = start;
+    let mut exported = false;
+    if let Some(after) = input[pos..].strip_prefix("export") {
+        if after.starts_with([' ', '\t']) {
+            exported = true;
+            pos += "export".len();
+            pos += input[pos..].len() - input[pos..].trim_start_matches([' ', '\t']).len();
+        }
+    }
+
+    let key_len = input[pos..]
+        .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-')))
+        .unwrap_or(input.len() - pos);
+    let key = &input[pos..pos + key_len];
+    if !is_valid_key(key) {
+        return Err(error("expected a variable name"));
+    }
+    pos += key_len;
+    pos += input[pos..].len() - input[pos..].trim_start_matches([' ', '\t']).len();
+    if !input[pos..].starts_with('=') {
+        return Err(error(&format!("expected '=' after '{}'", key)));
+    }
+    pos += 1;
+    pos += input[pos..].len() - input[pos..].trim_start_matches([' ', '\t']).len();
+
+    let (value, value_end) = match input[pos..].chars().next() {
+        Some(quote @ ('"' | '\'')) => {
+            let (value, len) = parse_quoted(&input[pos + 1..], quote)
+                .ok_or_else(|| error(&format!("unterminated {} quote", quote)))?;
+            (value, pos + 1 + len)
+        }
+        _ => {
+            let line_end = input[pos..].find('\n').map_or(input.len(), |i| pos + i);
+            let line = &input[pos..line_end];
+            // An unquoted value ends at a `#` that follows whitespace.
+            let value_len = line
+                .char_indices()
+                .find(|&(i, c)| c == '#' && (i == 0 || line[..i].ends_with([' ', '\t'])))
+                .map_or(line.len(), |(i, _)| i);
+            (line[..value_len].trim_end().to_string(), line_end)
+        }
+    };
+
+    // Only whitespace or a comment may follow a quoted value.
+    let line_end = input[value_end..]
+        .find('\n')
+        .map_or(input.len(), |i| value_end + i);
+    let trailing = input[value_end..line_end].trim();
+    if !trailing.is_empty() && !trailing.starts_with('#') {
+        return Err(error(&format!("unexpected text after value of '{}'", key)));
+    }
+    let consumed = (line_end + 1).min(input.len());
+
+    Ok((
+        Entry {
+            key: key.to_string(),
+            value,
+            exported,
+            raw: None,
+        },
+        consumed,
+    ))
+}
+
+/// Reads a quoted value up to its closing quote, returning the value and the bytes used
+/// including the closing quote. Double quotes understand `\n`, `\r`, `\t`, `\"` and `\\`.
+fn parse_quoted(input: &str, quote: char) -> Option<(String, usize)> {
+    let mut value = String::new();
+    let mut chars = input.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            c if c == quote => return Some((value, i + c.len_utf8())),
+            '\\' if quote == '"' => match chars.next()?.1 {
+                'n' => value.push('\n'),
+                'r' => value.push('\r'),
+                't' => value.push('\t'),
+                escaped @ ('"' | '\\' | '$') => value.push(escaped),
+                other => {
+                    value.push('\\');
+                    value.push(other);
+                }
+            },
+            '\r' if input[i + 1..].starts_with('\n') => {}
+            c => value.push(c),
+        }
+    }
+    None
+}
@@ -0,0 +1,332 @@
+use crate::dotenv::{is_valid_key, DotEnv};
+use base64::{engine::general_purpose, Engine as _};
+use std::str::FromStr;
+
+/// The formats a project's variables can be rendered in or imported from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Dotenv,
+    Shell,
+    Json,
+    Yaml,
+    Docker,
+    Systemd,
+    K8sSecret,
+}
+
+impl Format {
+    pub const NAMES: &'static [&'static str] = &[
+        "dotenv",
+        "shell",
+        "json",
+        "yaml",
+        "docker",
+        "systemd",
+        "k8s-secret",
+    ];
+    /// The formats `import` understands.
+    pub const IMPORT_NAMES: &'static [&'static str] =
+        &["dotenv", "shell", "json", "yaml", "k8s-secret"];
+
+    /// Renders the variables of a dotenv file. `name` is used where the format needs
+    /// one, such as the `metadata.name` of a Kubernetes Secret.
+    pub fn render(self, content: &str, name: &str) -> Result<String, String> {
+        let vars = DotEnv::parse(content)
+            .map_err(|e| format!("content is not a valid .env file ({})", e))?
+            .vars();
+
+        let mut out = String::new();
+        match self {
+            Format::Dotenv => {
+                for (key, value) in &vars {
+                    out += &format!("{}={}\n", key, crate::dotenv::quote(value));
+                }
+            }
+            Format::Shell => {
+                for (key, value) in &vars {
+                    // .env keys may contain `.` and `-`, which a shell variable name can't.
+                    if key.contains(['.', '-']) {
+                        return Err(format!("'{}' is not a valid shell variable name", key));
+                    }
+                    out += &format!("export {}='{}'\n", key, value.replace('\'', "'\\''"));
+                }
+            }
+            Format::Json => {
+                let fields: Vec<String> = vars
+                    .iter()
+                    .map(|(key, value)| format!("  {}: {}", json_string(key), json_string(value)))
+                    .collect();
+                if fields.is_empty() {
+                    out += "{}\n";
+                } else {
+                    out += &format!("{{\n{}\n}}\n", fields.join(",\n"));
+                }
+            }
+            Format::Yaml => {
+                for (key, value) in &vars {
+                    // Quoted, so keys like `null`, `yes` or `1e3` stay strings.
+                    out += &format!("{}: {}\n", json_string(key), json_string(value));
+                }
+            }
+            Format::Docker => {
+                // `docker run --env-file` takes every line literally: no quotes, no escapes.
+                for (key, value) in &vars {
+                    if value.contains(['\n', '\r']) {
+                        return Err(format!(
+                            "'{}' spans several lines, which a Docker env-file cannot hold",
+                            key
+                        ));
+                    }
+                    out += &format!("{}={}\n", key, value);
+                }
+            }
+            Format::Systemd => {
+                for (key, value) in &vars {
+                    let mut escaped = String::new();
+                    for c in value.chars() {
+                        if matches!(c, '\\' | '"' | '$' | '`') {
+                            escaped.push('\\');
+                        }
+                        escaped.push(c);
+                    }
+                    out += &format!("{}=\"{}\"\n", key, escaped);
+                }
+            }
+            Format::K8sSecret => {
+                out += "apiVersion: v1\nkind: Secret\nmetadata:\n";
+                out += &format!("  name: {}\n", k8s_name(name));
+                out += "type: Opaque\n";
+                if vars.is_empty() {
+                    out += "data: {}\n";
+                } else {
+                    out += "data:\n";
+                    for (key, value) in &vars {
+                        out += &format!(
+                            "  {}: {}\n",
+                            json_string(key),
+                            general_purpose::STANDARD.encode(value)
+                        );
+                    }
+                }
+            }
+        }
+        Ok(out)
+    }
+}
+
+impl Format {
+    /// Converts input in this format into dotenv content. Dotenv input is kept as is.
+    pub fn import(self, input: &str) -> Result<String, String> {
+        let vars = match self {
+            Format::Dotenv => return Ok(input.to_string()),
+            Format::Shell => parse_shell(input)?,
+            Format::Json => {
+                let value: serde_json::Value =
+                    serde_json::from_str(input).map_err(|e| format!("invalid JSON: {}", e))?;
+                flat_vars(&value)?
+            }
+            Format::Yaml => flat_vars(&parse_yaml(input)?)?,
+            Format::K8sSecret => {
+                let secret = parse_yaml(input)?;
+                match secret.get("kind").and_then(|kind| kind.as_str()) {
+                    Some("Secret") | None => {}
+                    Some(kind) => return Err(format!("expected a Secret, found a {}", kind)),
+                }
+                let mut vars = Vec::new();
+                if let Some(data) = secret.get("data").filter(|data| !data.is_null()) {
+                    for (key, value) in flat_vars(data)? {
+                        let decoded = general_purpose::STANDARD
+                            .decode(value.trim())
+                            .ok()
+                            .and_then(|bytes| String::from_utf8(bytes).ok())
+                            .ok_or_else(|| format!("'{}' is not valid base64 text", key))?;
+                        vars.push((key, decoded));
+                    }
+                }
+                if let Some(data) = secret.get("stringData").filter(|data| !data.is_null()) {
+                    vars.extend(flat_vars(data)?);
+                }
+                vars
+            }
+            Format::Docker | Format::Systemd => {
+                return Err(format!("importing from {} is not supported", self.name()))
+            }
+        };
+
+        let mut env = DotEnv::new();
+        for (key, value) in vars {
+            if !is_valid_key(&key) {
+                return Err(format!("'{}' is not a valid variable name", key));
+            }
+            env.set(&key, &value);
+        }
+        Ok(env.to_string())
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Format::Dotenv => "dotenv",
+            Format::Shell => "shell",
+            Format::Json => "json",
+            Format::Yaml => "yaml",
+            Format::Docker => "docker",
+            Format::Systemd => "systemd",
+            Format::K8sSecret => "k8s-secret",
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "dotenv" | "env" => Ok(Format::Dotenv),
+            "shell" | "sh" => Ok(Format::Shell),
+            "json" => Ok(Format::Json),
+            "yaml" | "yml" => Ok(Format::Yaml),
+            "docker" | "env-file" => Ok(Format::Docker),
+            "systemd" => Ok(Format::Systemd),
+            "k8s" | "k8s-secret" | "kubernetes" => Ok(Format::K8sSecret),
+            _ => Err(format!(
+                "Unknown format '{}', expected one of: {}",
+                s,
+                Format::NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+/// A double-quoted string, valid in both JSON and YAML.
+fn json_string(value: &str) -> String {
+    serde_json::Value::from(value).to_string()
+}
+
+/// Turns a project name into a valid Kubernetes object name.
+fn k8s_name(name: &str) -> String {
+    let name: String = name
+        .to_ascii_lowercase()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    let name = name.trim_matches(|c: char| !c.is_ascii_alphanumeric());
+    if name.is_empty() {
+        "dotenvpull".to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+fn parse_yaml(input: &str) -> Result<serde_json::Value, String> {
+    serde_yaml::from_str(input).map_err(|e| format!("invalid YAML: {}", e))
+}
+
+/// Reads a mapping of names to scalar values.
+fn flat_vars(value: &serde_json::Value) -> Result<Vec<(String, String)>, String> {
+    let object = value
+        .as_object()
+        .ok_or("expected a mapping of variable names to values")?;
+    object
+        .iter()
+        .map(|(key, value)| {
+            let value = match value {
+                serde_json::Value::String(s) => s.clone(),
+                serde_json::Value::Number(n) => n.to_string(),
+                serde_json::Value::Bool(b) => b.to_string(),
+                serde_json::Value::Null => String::new(),
+                _ => return Err(format!("'{}' is not a plain value", key)),
+            };
+            Ok((key.clone(), value))
+        })
+        .collect()
+}
+
+/// Reads `KEY=value` assignments as a POSIX shell would, with optional `export`,
+/// single quotes, double quotes and backslash escapes.
+fn parse_shell(input: &str) -> Result<Vec<(String, String)>, String> {
+    let mut vars = Vec::new();
+    let mut chars = input.chars().peekable();
+    let mut line = 1;
+
+    loop {
+        while let Some(&c) = chars.peek() {
+            match c {
+                '\n' => line += 1,
+                ' ' | '\t' | '\r' | ';' => {}
+                _ => break,
+            }
+            chars.next();
+        }
+        let Some(&first) = chars.peek() else {
+            return Ok(vars);
+        };
+        if first == '#' {
+            while chars.next_if(|&c| c != '\n').is_some() {}
+            continue;
+        }
+
+        let mut word = String::new();
+        while let Some(c) = chars.next_if(|&c| c != '=' && !c.is_whitespace()) {
+            word.push(c);
+        }
+        if word == "export" {
+            while chars.next_if(|&c| c == ' ' || c == '\t').is_some() {}
+            continue;
+        }
+        if chars.next() != Some('=') || !is_valid_key(&word) {
+            return Err(format!("line {}: expected KEY=value", line));
+        }
+
+        let mut value = String::new();
+        while let Some(&c) = chars.peek() {
+            if c.is_whitespace() || c == ';' {
+                break;
+            }
+            chars.next();
+            match c {
+                '\'' => loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => {
+                            line += usize::from(c == '\n');
+                            value.push(c);
+                        }
+                        None => return Err(format!("line {}: unterminated ' quote", line)),
+                    }
+                },
+                '"' => loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\' | '$' | '`')) => value.push(c),
+                            Some('\n') => line += 1,
+                            Some(c) => {
+                                value.push('\\');
+                                value.push(c);
+                            }
+                            None => return Err(format!("line {}: unterminated \" quote", line)),
+                        },
+                        Some(c) => {
+                            line += usize::from(c == '\n');
+                            value.push(c);
+                        }
+                        None => return Err(format!("line {}: unterminated \" quote", line)),
+                    }
+                },
+                '\\' => match chars.next() {
+                    Some('\n') => line += 1,
+                    Some(c) => value.push(c),
+                    None => {}
+                },
+                c => value.push(c),
+            }
+        }
+        vars.push((word, value));
+    }
+}
@@ -0,0 +1,10 @@
+pub mod api;
+pub mod bundle;
+pub mod cli;
+pub mod config;
+pub mod crypto;
+pub mod diff;
+pub mod dotenv;
+pub mod format;
+pub mod share_uri;
+pub mod utils;
@@ -0,0 +1,111 @@
+use base64::{engine::general_purpose, Engine as _};
+use std::fmt;
+
+const HTTPS_SCHEME: &str = "dotenvpull://";
+const HTTP_SCHEME: &str = "dotenvpull+http://";
+
+/// Everything `getshared` needs, packed into one copy-pasteable link:
+/// `dotenvpull://host/project#code.key`.
+///
+/// The share code and key sit in the fragment, which is never sent to the server.
+/// Servers reached over plain HTTP use the `dotenvpull+http://` scheme.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShareUri {
+    pub api_url: String,
+    pub project_id: String,
+    /// Standard base64, as sent to the server.
+    pub share_code: String,
+    /// Standard base64, as stored in the config.
+    pub encryption_key: String,
+}
+
+impl ShareUri {
+    pub fn parse(uri: &str) -> Result<Self, String> {
+        let invalid = || format!("Invalid share link '{}'", uri);
+
+        let (location, fragment) = uri.trim().split_once('#').ok_or_else(invalid)?;
+        let (api_url, path) = if let Some(rest) = location.strip_prefix(HTTP_SCHEME) {
+            ("http://", rest)
+        } else if let Some(rest) = location.strip_prefix(HTTPS_SCHEME) {
+            ("https://", rest)
+        } else {
+            return Err(invalid());
+        };
+        let (host, project_id) = path.rsplit_once('/').ok_or_else(invalid)?;
+        let (share_code, encryption_key) = fragment.split_once('.').ok_or_else(invalid)?;
+        if host.is_empty() || project_id.is_empty() {
+            return Err(invalid());
+        }
+
+        Ok(ShareUri {
+            api_url: format!("{}{}", api_url, host),
+            project_id: percent_decode(project_id).ok_or_else(invalid)?,
+            share_code: to_standard(share_code).ok_or_else(invalid)?,
+            encryption_key: to_standard(encryption_key).ok_or_else(invalid)?,
+        })
+    }
+}
+
+impl fmt::Display for ShareUri {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (scheme, host) = match self.api_url.strip_prefix("http://") {
+            Some(host) => (HTTP_SCHEME, host),
+            None => (
+                HTTPS_SCHEME,
+                self.api_url
+                    .strip_prefix("https://")
+                    .unwrap_or(&self.api_url),
+            ),
+        };
+        write!(
+            f,
+            "{}{}/{}#{}.{}",
+            scheme,
+            host.trim_end_matches('/'),
+            percent_encode(&self.project_id),
+            to_url_safe(&self.share_code),
+            to_url_safe(&self.encryption_key),
+        )
+    }
+}
+
+fn to_url_safe(standard: &str) -> String {
+    standard
+        .trim_end_matches('=')
+        .replace('+', "-")
+        .replace('/', "_")
+}
+
+fn to_standard(url_safe: &str) -> Option<String> {
+    let bytes = general_purpose::URL_SAFE_NO_PAD.decode(url_safe).ok()?;
+    Some(general_purpose::STANDARD.encode(bytes))
+}
+
+fn percent_encode(input: &str) -> String {
+    input
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+fn percent_decode(input: &str) -> Option<String> {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = input.get(i + 1..i + 3)?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
@@ -1,10 +1,88 @@
-use serde_json::Value;
+use rand::Rng;
+use reqwest::header::{HeaderMap, ETAG};
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
 
-pub fn list_projects(config: &Value) {
-    println!("Projects in local config:");
-    for (project, _) in config.as_object().unwrap() {
-        if project != "api_url" {
-            println!("- {}", project);
+/// Reads the project revision from a response's `ETag` header.
+pub fn etag_revision(headers: &HeaderMap) -> Option<u64> {
+    headers
+        .get(ETAG)?
+        .to_str()
+        .ok()?
+        .trim_start_matches("W/")
+        .trim_matches('"')
+        .parse()
+        .ok()
+}
+
+/// Parses a duration like `90`, `30s`, `15m`, `1h` or `7d` into seconds.
+pub fn parse_duration(input: &str) -> Result<u64, String> {
+    let input = input.trim();
+    let split = input
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(input.len());
+    let (amount, unit) = input.split_at(split);
+    let amount: u64 = amount
+        .parse()
+        .map_err(|_| format!("Invalid duration '{}'", input))?;
+    let multiplier = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => {
+            return Err(format!(
+                "Invalid duration unit in '{}', use s, m, h or d",
+                input
+            ))
         }
+    };
+    amount
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("Duration '{}' is too long", input))
+}
+
+/// Writes a file holding secrets or keys, so that it is never world-readable and never left
+/// half-written: the content goes to a temporary file with mode 0600 next to `path`, which is
+/// synced and then renamed over it. An existing file keeps its mode where that is tighter.
+pub fn write_secret_file(path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    let path = path.as_ref();
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file path"))?;
+    let temp_path = dir.join(format!(
+        ".{}.{:016x}.tmp",
+        name.to_string_lossy(),
+        rand::thread_rng().gen::<u64>()
+    ));
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        let mode = fs::metadata(path).map_or(0o600, |m| m.permissions().mode() & 0o600);
+        options.mode(mode);
+    }
+
+    let result = options.open(&temp_path).and_then(|mut file| {
+        file.write_all(contents.as_ref())?;
+        file.sync_all()?;
+        fs::rename(&temp_path, path)
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+        return result;
+    }
+    // Make the rename itself durable.
+    #[cfg(unix)]
+    if let Ok(dir) = fs::File::open(dir) {
+        let _ = dir.sync_all();
     }
+    Ok(())
 }
@@ -0,0 +1,64 @@
+use cli::bundle::Bundle;
+use std::fs;
+
+#[test]
+fn directories_and_globs_are_bundled_with_relative_paths() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().join("service");
+    fs::create_dir_all(root.join("config")).unwrap();
+    fs::write(root.join(".env"), "A=1\n").unwrap();
+    fs::write(root.join("config/local.toml"), "port = 1\n").unwrap();
+    fs::write(root.join("config/prod.env"), "B=2\n").unwrap();
+
+    let bundle = Bundle::collect(root.to_str().unwrap()).unwrap().unwrap();
+    assert_eq!(
+        bundle.paths(),
+        [".env", "config/local.toml", "config/prod.env"]
+    );
+
+    let pattern = format!("{}/**/*.env", root.display());
+    let bundle = Bundle::collect(&pattern).unwrap().unwrap();
+    assert_eq!(bundle.paths(), [".env", "config/prod.env"]);
+
+    assert_eq!(
+        Bundle::collect(root.join(".env").to_str().unwrap()).unwrap(),
+        None
+    );
+    let nothing = format!("{}/*.missing", root.display());
+    assert!(Bundle::collect(&nothing).is_err());
+}
+
+#[test]
+fn bundles_round_trip_and_extract() {
+    let dir = tempfile::tempdir().unwrap();
+    let src = dir.path().join("src");
+    fs::create_dir_all(src.join("keys")).unwrap();
+    fs::write(src.join(".env"), "A=1\n").unwrap();
+    fs::write(src.join("keys/sa.json"), [0u8, 159, 146, 150]).unwrap();
+
+    let bundle = Bundle::collect(src.to_str().unwrap()).unwrap().unwrap();
+    let decoded = Bundle::decode(&bundle.encode()).unwrap();
+    assert_eq!(decoded, bundle);
+    assert_eq!(Bundle::decode("A=1\n"), None);
+    assert_eq!(Bundle::decode("{\"files\": []}"), None);
+
+    let out = dir.path().join("out");
+    decoded.extract(&out, false).unwrap();
+    assert_eq!(fs::read(out.join(".env")).unwrap(), b"A=1\n");
+    assert_eq!(
+        fs::read(out.join("keys/sa.json")).unwrap(),
+        [0u8, 159, 146, 150]
+    );
+
+    assert!(decoded.extract(&out, false).is_err());
+    decoded.extract(&out, true).unwrap();
+}
+
+#[test]
+fn paths_escaping_the_output_directory_are_refused() {
+    let dir = tempfile::tempdir().unwrap();
+    let evil = r#"{"format":"dotenvpull-bundle/1","files":[{"path":"../evil","content":"eA=="}]}"#;
+    let bundle = Bundle::decode(evil).unwrap();
+    assert!(bundle.extract(&dir.path().join("out"), true).is_err());
+    assert!(!dir.path().join("evil").exists());
+}
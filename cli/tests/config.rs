@@ -0,0 +1,71 @@
+use cli::config::{Config, CONFIG_VERSION, DEFAULT_SERVER};
+
+#[test]
+fn flat_configs_are_migrated() {
+    let config = Config::parse(
+        r#"{
+            "api_url": "http://example.com",
+            "app": {"access_key": "ak", "encryption_key": "ek", "revision": 3}
+        }"#,
+    )
+    .unwrap();
+
+    assert_eq!(config.version, CONFIG_VERSION);
+    assert_eq!(config.api_url(), "http://example.com");
+    assert_eq!(config.defaults.server, DEFAULT_SERVER);
+    let project = config.project("app").unwrap();
+    assert_eq!(project.access_key, "ak");
+    assert_eq!(project.revision("default"), Some(3));
+}
+
+#[test]
+fn projects_can_use_names_the_flat_layout_reserved() {
+    let config = Config::parse(
+        r#"{
+            "version": 2,
+            "servers": {"default": {"api_url": "http://example.com"}},
+            "projects": {"api_url": {"access_key": "ak", "encryption_key": "ek"}}
+        }"#,
+    )
+    .unwrap();
+
+    assert_eq!(config.api_url(), "http://example.com");
+    assert_eq!(config.project("api_url").unwrap().access_key, "ak");
+}
+
+#[test]
+fn flat_configs_can_have_projects_named_like_top_level_fields() {
+    let config = Config::parse(
+        r#"{
+            "api_url": "http://example.com",
+            "version": {"access_key": "ak1", "encryption_key": "ek1"},
+            "app": {"access_key": "ak2", "encryption_key": "ek2"}
+        }"#,
+    )
+    .unwrap();
+
+    assert_eq!(config.version, CONFIG_VERSION);
+    assert_eq!(config.api_url(), "http://example.com");
+    assert_eq!(config.project("version").unwrap().access_key, "ak1");
+    assert_eq!(config.project("app").unwrap().access_key, "ak2");
+
+    let config =
+        Config::parse(r#"{"api_url": {"access_key": "ak", "encryption_key": "ek"}}"#).unwrap();
+    assert_eq!(config.project("api_url").unwrap().access_key, "ak");
+}
+
+#[test]
+fn malformed_projects_are_named_in_the_error() {
+    let err = Config::parse(r#"{"app": {"access_key": "ak"}}"#).unwrap_err();
+    assert!(err.starts_with("project 'app':"), "{}", err);
+
+    let err = Config::parse(r#"{"app": {"access_key": "ak", "encryption_key": "ek", "extra": 1}}"#)
+        .unwrap_err();
+    assert!(err.contains("extra"), "{}", err);
+}
+
+#[test]
+fn newer_configs_are_rejected() {
+    let err = Config::parse(r#"{"version": 99, "projects": {}}"#).unwrap_err();
+    assert!(err.contains("newer version"), "{}", err);
+}
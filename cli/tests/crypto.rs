@@ -0,0 +1,112 @@
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::{engine::general_purpose, Engine as _};
+use cli::crypto::{
+    decrypt, decrypt_bytes, derive_passphrase_key, encrypt, encrypt_bytes, unwrap_with_passphrase,
+    wrap_with_passphrase, ContentType, CryptoError,
+};
+
+const KEY: [u8; 32] = [7; 32];
+
+#[test]
+fn text_and_binary_round_trip() {
+    let encrypted = encrypt("A=1\n", &KEY);
+    assert_eq!(decrypt(&encrypted, &KEY).unwrap(), "A=1\n");
+    assert_eq!(
+        decrypt_bytes(&encrypted, &KEY).unwrap(),
+        (ContentType::Text, b"A=1\n".to_vec())
+    );
+
+    let data = vec![0, 159, 146, 150, 255];
+    let encrypted = encrypt_bytes(&data, ContentType::Binary, &KEY);
+    assert_eq!(
+        decrypt_bytes(&encrypted, &KEY).unwrap(),
+        (ContentType::Binary, data)
+    );
+}
+
+#[test]
+fn payloads_without_a_content_type_are_read_as_text() {
+    let nonce = [3u8; 12];
+    let ciphertext = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&KEY))
+        .encrypt(Nonce::from_slice(&nonce), b"LEGACY=yes\n".as_slice())
+        .unwrap();
+    let legacy = general_purpose::STANDARD.encode([nonce.as_slice(), &ciphertext].concat());
+
+    assert_eq!(decrypt(&legacy, &KEY).unwrap(), "LEGACY=yes\n");
+    assert_eq!(
+        decrypt(&legacy, &[8; 32]),
+        Err(CryptoError::AuthenticationFailed)
+    );
+}
+
+#[test]
+fn damaged_data_and_wrong_keys_are_errors() {
+    let encrypted = encrypt("A=1\n", &KEY);
+
+    assert_eq!(decrypt("not base64!", &KEY), Err(CryptoError::BadEncoding));
+    assert_eq!(decrypt(&encrypted[..20], &KEY), Err(CryptoError::Truncated));
+    assert_eq!(decrypt(&encrypted, &[8; 32]), Err(CryptoError::KeyMismatch));
+    let binary = encrypt_bytes(&[0xff, 0xfe], ContentType::Binary, &KEY);
+    assert_eq!(decrypt(&binary, &KEY), Err(CryptoError::InvalidUtf8));
+}
+
+/// Decodes an envelope, applies `edit` to its bytes and encodes it again.
+fn tamper(encrypted: &str, edit: impl FnOnce(&mut Vec<u8>)) -> String {
+    let mut bytes = general_purpose::STANDARD.decode(encrypted).unwrap();
+    edit(&mut bytes);
+    general_purpose::STANDARD.encode(bytes)
+}
+
+#[test]
+fn envelope_header_is_authenticated() {
+    let encrypted = encrypt("A=1\n", &KEY);
+    assert!(general_purpose::STANDARD
+        .decode(&encrypted)
+        .unwrap()
+        .starts_with(b"DEP\x01\x01"));
+
+    let newer = tamper(&encrypted, |bytes| bytes[3] = 2);
+    assert_eq!(decrypt(&newer, &KEY), Err(CryptoError::UnsupportedFormat));
+    let flagged = tamper(&encrypted, |bytes| bytes[5] = 1);
+    assert_eq!(
+        decrypt(&flagged, &KEY),
+        Err(CryptoError::AuthenticationFailed)
+    );
+}
+
+#[test]
+fn large_content_is_compressed() {
+    let content = "LINE=some repeated value\n".repeat(200);
+    let encrypted = encrypt(&content, &KEY);
+    assert!(encrypted.len() < content.len());
+    assert_eq!(decrypt(&encrypted, &KEY).unwrap(), content);
+}
+
+#[test]
+fn passphrase_kdf_costs_are_capped() {
+    let wrapped = wrap_with_passphrase("A=1\n", "right");
+    assert_eq!(unwrap_with_passphrase(&wrapped, "right").unwrap(), "A=1\n");
+    assert_eq!(
+        unwrap_with_passphrase(&wrapped, "wrong"),
+        Err(CryptoError::KeyMismatch)
+    );
+
+    // A KDF string from the server can't make the client spend gigabytes or minutes.
+    let salt = general_purpose::STANDARD.encode([1u8; 16]);
+    for settings in [
+        "m=4194304,t=2,p=1",
+        "m=19456,t=1000,p=1",
+        "m=19456,t=2,p=64",
+    ] {
+        let kdf = format!("argon2id${}${}", settings, salt);
+        assert_eq!(
+            derive_passphrase_key(&kdf, "x"),
+            Err(CryptoError::KdfTooCostly)
+        );
+    }
+    assert_eq!(
+        derive_passphrase_key("argon2id$m=19456$salt", "x"),
+        Err(CryptoError::BadEncoding)
+    );
+}
@@ -0,0 +1,36 @@
+use cli::diff::render;
+
+#[test]
+fn dotenv_files_get_a_masked_key_diff() {
+    let remote = "A=1\nB=2\nC=3\n";
+    let local = "# comment\nA=1\nB=changed\nD=4\n";
+
+    assert_eq!(
+        render(remote, local, false).unwrap(),
+        "~ B: **** -> ****\n- C=****\n+ D=****"
+    );
+    assert_eq!(
+        render(remote, local, true).unwrap(),
+        "~ B: 2 -> changed\n- C=3\n+ D=4"
+    );
+}
+
+#[test]
+fn identical_content_has_no_diff() {
+    assert_eq!(render("A=1\n", "A=1\n", false), None);
+    assert_eq!(
+        render("A=1\n", "# note\nA='1'\n", false).unwrap(),
+        "Variables are identical; only comments or formatting differ."
+    );
+}
+
+#[test]
+fn other_files_get_a_line_diff_with_context() {
+    let remote = "[server]\nport = 80\nhost = \"a\"\n\n[db]\nurl = \"x\"\npool = 5\nretries = 3\n";
+    let local = "[server]\nport = 8080\nhost = \"a\"\n\n[db]\nurl = \"x\"\npool = 5\nretries = 3\ntimeout = 1\n";
+
+    assert_eq!(
+        render(remote, local, false).unwrap(),
+        "  [server]\n- port = 80\n+ port = 8080\n  host = \"a\"\n  \n...\n  pool = 5\n  retries = 3\n+ timeout = 1"
+    );
+}
@@ -0,0 +1,62 @@
+use cli::dotenv::DotEnv;
+
+#[test]
+fn parses_quotes_comments_exports_and_multiline_values() {
+    let env = DotEnv::parse(
+        r#"# database
+export DB_HOST=localhost
+DB_PORT = 5432  # default port
+PASSWORD="p#ss \"word\"\n"
+LITERAL='no $expansion \n here'
+URL=http://example.com/#anchor
+EMPTY=
+CERT="-----BEGIN-----
+abc
+-----END-----"
+"#,
+    )
+    .unwrap();
+
+    assert_eq!(env.get("DB_HOST"), Some("localhost"));
+    assert_eq!(env.get("DB_PORT"), Some("5432"));
+    assert_eq!(env.get("PASSWORD"), Some("p#ss \"word\"\n"));
+    assert_eq!(env.get("LITERAL"), Some("no $expansion \\n here"));
+    assert_eq!(env.get("URL"), Some("http://example.com/#anchor"));
+    assert_eq!(env.get("EMPTY"), Some(""));
+    assert_eq!(env.get("CERT"), Some("-----BEGIN-----\nabc\n-----END-----"));
+    assert_eq!(env.get("MISSING"), None);
+    assert_eq!(env.vars().len(), 7);
+}
+
+#[test]
+fn edits_keep_the_rest_of_the_file_intact() {
+    let source = "# keep me\nexport A=1 # one\n\nB='two'\nA=3\n";
+    let mut env = DotEnv::parse(source).unwrap();
+    assert_eq!(env.to_string(), source);
+    assert_eq!(env.get("A"), Some("3"));
+
+    env.set("B", "has spaces and \"quotes\"\nand lines");
+    env.set("C", "new");
+    assert!(env.unset("A"));
+    assert!(!env.unset("A"));
+    assert_eq!(
+        env.to_string(),
+        "# keep me\n\nB=\"has spaces and \\\"quotes\\\"\\nand lines\"\nC=new\n"
+    );
+
+    let reparsed = DotEnv::parse(&env.to_string()).unwrap();
+    assert_eq!(reparsed.vars(), env.vars());
+}
+
+#[test]
+fn malformed_lines_report_their_line_number() {
+    for (source, line) in [
+        ("A=1\nnot a variable\n", 2),
+        ("A=1\n\nB=\"unterminated\n", 3),
+        ("1ABC=1\n", 1),
+        ("A='x' trailing\n", 1),
+    ] {
+        let err = DotEnv::parse(source).unwrap_err();
+        assert_eq!(err.line, line, "{:?}: {}", source, err);
+    }
+}
@@ -0,0 +1,2189 @@
+//! End-to-end tests that drive the real CLI functions against an in-process server
+//! backed by `MemoryStorage`.
+
+use actix_web::guard::{self, GuardContext};
+use actix_web::{dev::ServerHandle, web, App, HttpResponse, HttpServer};
+use backend::storage::memory::MemoryStorage;
+use backend::storage::sqlite::SqliteStorage;
+use backend::storage::Storage;
+use backend::{configure, AppState};
+use base64::{engine::general_purpose, Engine as _};
+use cli::api::{
+    delete, diff, export, get_base, get_var, getshared, history, pull, push, resume_rotation,
+    rollback, rotate_key, run, set_base, set_var, share, unset_var, update,
+};
+use cli::config::{
+    config_path, forget_passphrase, load_config, load_config_locked, lock_config,
+    remove_config_lock, save_config, set_config_path, set_default_server, set_server,
+    update_config, Config, CONFIG_ENV, DEFAULT_ENVIRONMENT, DEFAULT_SERVER,
+};
+use cli::format::Format;
+use cli::share_uri::ShareUri;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tempfile::TempDir;
+use tokio::sync::{Mutex, MutexGuard};
+
+/// The CLI finds its config through the working directory and environment variables,
+/// which are shared by the whole process, so tests take turns.
+static CWD_LOCK: Mutex<()> = Mutex::const_new(());
+
+struct TestEnv {
+    api_url: String,
+    server: ServerHandle,
+    _guard: MutexGuard<'static, ()>,
+}
+
+impl TestEnv {
+    async fn start() -> Self {
+        Self::start_with(Arc::new(MemoryStorage::new())).await
+    }
+
+    async fn start_with(storage: Arc<dyn Storage>) -> Self {
+        Self::serve(storage, None).await
+    }
+
+    /// A server behind a proxy that only lets requests with `token` as bearer token through.
+    async fn start_with_token(token: &'static str) -> Self {
+        Self::serve(Arc::new(MemoryStorage::new()), Some(token)).await
+    }
+
+    async fn serve(storage: Arc<dyn Storage>, token: Option<&'static str>) -> Self {
+        let guard = CWD_LOCK.lock().await;
+        let state = web::Data::new(AppState { storage });
+        let authorized = move |ctx: &GuardContext| {
+            token.is_none_or(|token| {
+                ctx.head()
+                    .headers()
+                    .get("Authorization")
+                    .is_some_and(|value| *value == format!("Bearer {}", token))
+            })
+        };
+        let server = HttpServer::new(move || {
+            App::new().app_data(state.clone()).service(
+                web::scope("")
+                    .guard(guard::fn_guard(authorized))
+                    .configure(configure),
+            )
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let api_url = format!("http://{}", server.addrs()[0]);
+        let server = server.run();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+        TestEnv {
+            api_url,
+            server: handle,
+            _guard: guard,
+        }
+    }
+
+    async fn stop(self) {
+        self.server.stop(true).await;
+    }
+}
+
+/// A fresh working directory, standing in for a separate machine.
+struct Workstation {
+    dir: TempDir,
+}
+
+impl Workstation {
+    fn new() -> Self {
+        let workstation = Workstation {
+            dir: tempfile::tempdir().unwrap(),
+        };
+        workstation.enter();
+        workstation
+    }
+
+    /// Makes this the working directory, with its own `dotenvpull_config.json`.
+    fn enter(&self) {
+        std::env::set_current_dir(self.dir.path()).unwrap();
+        std::env::set_var(CONFIG_ENV, self.path("dotenvpull_config.json"));
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.path().join(name)
+    }
+
+    fn write(&self, name: &str, content: &str) -> String {
+        let path = self.path(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, content).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+}
+
+fn read(path: impl AsRef<Path>) -> String {
+    fs::read_to_string(path).unwrap()
+}
+
+#[actix_web::test]
+async fn push_pull_update_delete_round_trip() {
+    let env = TestEnv::start().await;
+    let ws = Workstation::new();
+
+    let env_file = ws.write(".env", "API_KEY=first\n");
+    push(
+        &env.api_url,
+        "service",
+        &env_file,
+        Format::Dotenv,
+        DEFAULT_ENVIRONMENT,
+    )
+    .await
+    .unwrap();
+    let config = load_config().unwrap();
+    assert!(config.project("service").unwrap().key().is_ok());
+
+    let out = ws.path("pulled.env");
+    pull(
+        &env.api_url,
+        "service",
+        out.to_str().unwrap(),
+        false,
+        Format::Dotenv,
+        DEFAULT_ENVIRONMENT,
+        true,
+    )
+    .await
+    .unwrap();
+    assert_eq!(read(&out), "API_KEY=first\n");
+
+    ws.write(".env", "API_KEY=second\n");
+    update(
+        &env.api_url,
+        "service",
+        &env_file,
+        false,
+        Format::Dotenv,
+        DEFAULT_ENVIRONMENT,
+        true,
+    )
+    .await
+    .unwrap();
+    pull(
+        &env.api_url,
+        "service",
+        out.to_str().unwrap(),
+        true,
+        Format::Dotenv,
+        DEFAULT_ENVIRONMENT,
+        true,
+    )
+    .await
+    .unwrap();
+    assert_eq!(read(&out), "API_KEY=second\n");
+
+    delete(&env.api_url, "service", None).await.unwrap();
+    assert!(!load_config().unwrap().projects.contains_key("service"));
+
+    env.stop().await;
+}
+
+#[actix_web::test]
+async fn pull_does_not_overwrite_without_force() {
+    let env = TestEnv::start().await;
+    let ws = Workstation::new();
+
+    let env_file = ws.write(".env", "REMOTE=1\n");
+    push(
+        &env.api_url,
+        "service",
+        &env_file,
+        Format::Dotenv,
+        DEFAULT_ENVIRONMENT,
+    )
+    .await
+    .unwrap();
+
+    let out = ws.write("existing.env", "LOCAL=1\n");
+    pull(
+        &env.api_url,
+        "service",
+        &out,
+        false,
+        Format::Dotenv,
+        DEFAULT_ENVIRONMENT,
+        true,
+    )
+    .await
+    .unwrap();
+    assert_eq!(read(&out), "LOCAL=1\n");
+
+    pull(
+        &env.api_url,
+        "service",
+        &out,
+        true,
+        Format::Dotenv,
+        DEFAULT_ENVIRONMENT,
+        true,
+    )
+    .await
+    .unwrap();
+    assert_eq!(read(&out), "REMOTE=1\n");
+
+    env.stop().await;
+}
+
+#[actix_web::test]
+async fn rollback_restores_an_earlier_version() {
+    let env = TestEnv::start().await;
+    let ws = Workstation::new();
+
+    let env_file = ws.write(".env", "STAGE=one\n");
+    push(
+        &env.api_url,
+        "service",
+        &env_file,
+        Format::Dotenv,
+        DEFAULT_ENVIRONMENT,
+    )
+    .await
+    .unwrap();
+    ws.write(".env", "STAGE=two\n");
+    update(
+        &env.api_url,
+        "service",
+        &env_file,
+        false,
+        Format::Dotenv,
+        DEFAULT_ENVIRONMENT,
+        true,
+    )
+    .await
+    .unwrap();
+    history(&env.api_url, "service", DEFAULT_ENVIRONMENT)
+        .await
+        .unwrap();
+
+    rollback(&env.api_url, "service", 1, DEFAULT_ENVIRONMENT)
+        .await
+        .unwrap();
+    let out = ws.path("pulled.env");
+    pull(
+        &env.api_url,
+        "service",
+        out.to_str().unwrap(),
+        false,
+        Format::Dotenv,
+        DEFAULT_ENVIRONMENT,
+        true,
+    )
+    .await
+    .unwrap();
+    assert_eq!(read(&out), "STAGE=one\n");
+
+    // The rollback is itself a new version, so the bad update can still be recovered.
+    rollback(&env.api_url, "service", 2, DEFAULT_ENVIRONMENT)
+        .await
+        .unwrap();
+    pull(
+        &env.api_url,
+        "service",
+        out.to_str().unwrap(),
+        true,
+        Format::Dotenv,
+        DEFAULT_ENVIRONMENT,
+        true,
+    )
+    .await
+    .unwrap();
+    assert_eq!(read(&out), "STAGE=two\n");
+
+    env.stop().await;
+}
+
+#[actix_web::test]
+async fn stale_update_is_refused_unless_forced() {
+    let env = TestEnv::start().await;
+
+    let alice = Workstation::new();
+    let alice_file = alice.write(".env", "OWNER=alice\n");
+    push(
+        &env.api_url,
+        "service",
+        &alice_file,
+        Format::Dotenv,
+        DEFAULT_ENVIRONMENT,
+    )
+    .await
+    .unwrap();
+
+    let bob = Workstation::new();
+    fs::copy(
+        alice.path("dotenvpull_config.json"),
+        bob.path("dotenvpull_config.json"),
+    )
+    .unwrap();
+    let bob_file = bob.write(".env", "OWNER=bob\n");
+    update(
+        &env.api_url,
+        "service",
+        &bob_file,
+        false,
+        Format::Dotenv,
+        DEFAULT_ENVIRONMENT,
+        true,
+    )
+    .await
+    .unwrap();
+
+    alice.enter();
+    alice.write(".env", "OWNER=alice-again\n");
+    update(
+        &env.api_url,
+        "service",
+        &alice_file,
+        false,
+        Format::Dotenv,
+        DEFAULT_ENVIRONMENT,
+        true,
+    )
+    .await
+    .unwrap();
+    let out = alice.path("remote.env");
+    pull(
+        &env.api_url,
+        "service",
+        out.to_str().unwrap(),
+        false,
+        Format::Dotenv,
+        DEFAULT_ENVIRONMENT,
+        true,
+    )
+    .await
+    .unwrap();
+    assert_eq!(read(&out), "OWNER=bob\n");
+
+    // After pulling, alice is up to date and can update normally.
+    update(
+        &env.api_url,
+        "service",
+        &alice_file,
+        false,
+        Format::Dotenv,
+        DEFAULT_ENVIRONMENT,
+        true,
+    )
+    .await
+    .unwrap();
+    pull(
+        &env.api_url,
+        "service",
+        out.to_str().unwrap(),
+        true,
+        Format::Dotenv,
+        DEFAULT_ENVIRONMENT,
+        true,
+    )
+    .await
+    .unwrap();
+    assert_eq!(read(&out), "OWNER=alice-again\n");
+
+    bob.enter();
+    bob.write(".env", "OWNER=bob-forced\n");
+    update(
+        &env.api_url,
+        "service",
+        &bob_file,
+        true,
+        Format::Dotenv,
+        DEFAULT_ENVIRONMENT,
+        true,
+    )
+    .await
+    .unwrap();
+    let out = bob.path("remote.env");
+    pull(
+        &env.api_url,
+        "service",
+        out.to_str().unwrap(),
+        false,
+        Format::Dotenv,
+        DEFAULT_ENVIRONMENT,
+        true,
+    )
+    .await
+    .unwrap();
+    assert_eq!(read(&out), "OWNER=bob-forced\n");
+
+    env.stop().await;
+}
+
+#[actix_web::test]
+async fn sqlite_storage_keeps_history() {
+    let db_dir = tempfile::tempdir().unwrap();
+    let db_path = db_dir.path().join("dotenvpull.db");
+    let storage = SqliteStorage::open(db_path.to_str().unwrap()).unwrap();
+    let env = TestEnv::start_with(Arc::new(storage)).await;
+    let ws = Workstation::new();
+
+    let env_file = ws.write(".env", "STAGE=one\n");
+    push(
+        &env.api_url,
+        "service",
+        &env_file,
+        Format::Dotenv,
+        DEFAULT_ENVIRONMENT,
+    )
+    .await
+    .unwrap();
+    ws.write(".env", "STAGE=two\n");
+    update(
+        &env.api_url,
+        "service",
+        &env_file,
+        false,
+        Format::Dotenv,
+        DEFAULT_ENVIRONMENT,
+        true,
+    )
+    .await
+    .unwrap();
+    rollback(&env.api_url, "service", 1, DEFAULT_ENVIRONMENT)
+        .await
+        .unwrap();
+
+    let out = ws.path("pulled.env");
+    pull(
+        &env.api_url,
+        "service",
+        out.to_str().unwrap(),
+        false,
+        Format::Dotenv,
+        DEFAULT_ENVIRONMENT,
+        true,
+    )
+    .await
+    .unwrap();
+    assert_eq!(read(&out), "STAGE=one\n");
+
+    env.stop().await;
+}
+
+#[actix_web::test]
+async fn share_round_trip_can_only_be_claimed_once() {
+    let env = TestEnv::start().await;
+
+    let sender = Workstation::new();
+    let env_file = sender.write(".env", "SHARED=yes\n");
+    push(
+        &env.api_url,
+        "service",
+        &env_file,
+        Format::Dotenv,
+        DEFAULT_ENVIRONMENT,
+    )
+    .await
+    .unwrap();
+    let credentials = share(&env.api_url, "service", false, None, None, None)
+        .await
+        .unwrap()
+        .expect("share should succeed");
+
+    // The recipient only gets the printed link.
+    let link = ShareUri::parse(&credentials.to_string()).unwrap();
+    assert_eq!(link, credentials);
+
+    let receiver = Workstation::new();
+    getshared(
+        &link.api_url,
+        &link.share_code,
+        &link.project_id,
+        &link.encryption_key,
+        None,
+    )
+    .await
+    .unwrap();
+    let out = receiver.path("received.env");
+    pull(
+        &env.api_url,
+        "service",
+        out.to_str().unwrap(),
+        false,
+        Format::Dotenv,
+        DEFAULT_ENVIRONMENT,
+        true,
+    )
+    .await
+    .unwrap();
+    assert_eq!(read(&out), "SHARED=yes\n");
+
+    let latecomer = Workstation::new();
+    getshared(
+        &env.api_url,
+        &credentials.share_code,
+        "service",
+        &credentials.encryption_key,
+        None,
+    )
+    .await
+    .unwrap();
+    assert!(!load_config().unwrap().projects.contains_key("service"));
+    drop(latecomer);
+
+    env.stop().await;
+}
+
+#[actix_web::test]
+async fn flat_single_project_share_is_added_to_the_config() {
+    let env = TestEnv::start().await;
+    let ws = Workstation::new();
+    let env_file = ws.write(".env", "MINE=1\n");
+    push(
+        &env.api_url,
+        "mine",
+        &env_file,
+        Format::Dotenv,
+        DEFAULT_ENVIRONMENT,
+    )
+    .await
+    .unwrap();
+
+    // Older versions shared a single project as the flat layout, without `api_url`.
+    let encryption_key = [7u8; 32];
+    let flat = r#"{"svc": {"access_key": "ak", "encryption_key": "ek", "revision": 2}}"#;
+    let share_code = general_purpose::STANDARD.encode([9u8; 32]);
+    let response = reqwest::Client::new()
+        .post(format!("{}/share", env.api_url))
+        .json(&serde_json::json!({
+            "project_id": "svc",
+            "encrypted_content": cli::crypto::encrypt(flat, &encryption_key),
+            "share_code": share_code,
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert!(response.status().is_success());
+
+    getshared(
+        &env.api_url,
+        &share_code,
+        "svc",
+        &general_purpose::STANDARD.encode(encryption_key),
+        None,
+    )
+    .await
+    .unwrap();
+    let config = load_config().unwrap();
+    assert!(config.projects.contains_key("mine"));
+    assert_eq!(config.projects["svc"].access_key, "ak");
+    assert_eq!(
+        config.projects["svc"].revision(DEFAULT_ENVIRONMENT),
+        Some(2)
+    );
+
+    env.stop().await;
+}
+
+#[actix_web::test]
+async fn multi_use_share_can_be_claimed_until_used_up() {
+    let env = TestEnv::start().await;
+
+    let sender = Workstation::new();
+    let env_file = sender.write(".env", "TEAM=yes\n");
+    push(
+        &env.api_url,
+        "service",
+        &env_file,
+        Format::Dotenv,
+        DEFAULT_ENVIRONMENT,
+    )
+    .await
+    .unwrap();
+    let credentials = share(&env.api_url, "service", false, Some(3600), Some(2), None)
+        .await
+        .unwrap()
+        .expect("share should succeed");
+
+    for _ in 0..2 {
+        let receiver = Workstation::new();
+        getshared(
+            &env.api_url,
+            &credentials.share_code,
+            "service",
+            &credentials.encryption_key,
+            None,
+        )
+        .await
+        .unwrap();
+        let out = receiver.path("received.env");
+        pull(
+            &env.api_url,
+            "service",
+            out.to_str().unwrap(),
+            false,
+            Format::Dotenv,
+            DEFAULT_ENVIRONMENT,
+            true,
+        )
+        .await
+        .unwrap();
+        assert_eq!(read(&out), "TEAM=yes\n");
+    }
+
+    let _latecomer = Workstation::new();
+    getshared(
+        &env.api_url,
+        &credentials.share_code,
+        "service",
+        &credentials.encryption_key,
+        None,
+    )
+    .await
+    .unwrap();
+    assert!(!load_config().unwrap().projects.contains_key("service"));
+
+    env.stop().await;
+}
+
+#[actix_web::test]
+async fn expired_share_is_refused_and_can_be_replaced() {
+    let env = TestEnv::start().await;
+
+    let sender = Workstation::new();
+    let env_file = sender.write(".env", "LATE=yes\n");
+    push(
+        &env.api_url,
+        "service",
+        &env_file,
+        Format::Dotenv,
+        DEFAULT_ENVIRONMENT,
+    )
+    .await
+    .unwrap();
+    let expired = share(&env.api_url, "service", false, Some(0), None, None)
+        .await
+        .unwrap()
+        .expect("share should succeed");
+
+    let receiver = Workstation::new();
+    getshared(
+        &env.api_url,
+        &expired.share_code,
+        "service",
+        &expired.encryption_key,
+        None,
+    )
+    .await
+    .unwrap();
+    assert!(!load_config().unwrap().projects.contains_key("service"));
+
+    sender.enter();
+    let fresh = share(&env.api_url, "service", false, None, None, None)
+        .await
+        .unwrap()
+        .expect("an expired share should not block a new one");
+
+    receiver.enter();
+    getshared(
+        &env.api_url,
+        &fresh.share_code,
+        "service",
+        &fresh.encryption_key,
+        None,
+    )
+    .await
+    .unwrap();
+    assert!(load_config().unwrap().projects.contains_key("service"));
+
+    env.stop().await;
+}
+
+#[actix_web::test]
+async fn passphrase_protected_share_needs_the_passphrase() {
+    let env = TestEnv::start().await;
+
+    let sender = Workstation::new();
+    let env_file = sender.write(".env", "GUARDED=yes\n");
+    push(
+        &env.api_url,
+        "service",
+        &env_file,
+        Format::Dotenv,
+        DEFAULT_ENVIRONMENT,
+    )
+    .await
+    .unwrap();
+    let credentials = share(
+        &env.api_url,
+        "service",
+        false,
+        None,
+        Some(2),
+        Some("correct horse"),
+    )
+    .await
+    .unwrap()
+    .expect("share should succeed");
+
+    let receiver = Workstation::new();
+    let wrong = getshared(
+        &env.api_url,
+        &credentials.share_code,
+        "service",
+        &credentials.encryption_key,
+        Some("battery staple"),
+    )
+    .await;
+    assert!(wrong.is_err());
+    assert!(!load_config().unwrap().projects.contains_key("service"));
+
+    getshared(
+        &env.api_url,
+        &credentials.share_code,
+        "service",
+        &credentials.encryption_key,
+        Some("correct horse"),
+    )
+    .await
+    .unwrap();
+    let out = receiver.path("received.env");
+    pull(
+        &env.api_url,
+        "service",
+        out.to_str().unwrap(),
+        false,
+        Format::Dotenv,
+        DEFAULT_ENVIRONMENT,
+        true,
+    )
+    .await
+    .unwrap();
+    assert_eq!(read(&out), "GUARDED=yes\n");
+
+    env.stop().await;
+}
+
+#[actix_web::test]
+async fn sharing_all_projects_replaces_the_receivers_config() {
+    let env = TestEnv::start().await;
+
+    let sender = Workstation::new();
+    let first = sender.write("first.env", "FIRST=1\n");
+    let second = sender.write("second.env", "SECOND=2\n");
+    push(
+        &env.api_url,
+        "first",
+        &first,
+        Format::Dotenv,
+        DEFAULT_ENVIRONMENT,
+    )
+    .await
+    .unwrap();
+    push(
+        &env.api_url,
+        "second",
+        &second,
+        Format::Dotenv,
+        DEFAULT_ENVIRONMENT,
+    )
+    .await
+    .unwrap();
+    let credentials = share(&env.api_url, "first", true, None, None, None)
+        .await
+        .unwrap()
+        .expect("share should succeed");
+
+    let receiver = Workstation::new();
+    getshared(
+        &env.api_url,
+        &credentials.share_code,
+        "first",
+        &credentials.encryption_key,
+        None,
+    )
+    .await
+    .unwrap();
+    let out = receiver.path("second.env");
+    pull(
+        &env.api_url,
+        "second",
+        out.to_str().unwrap(),
+        false,
+        Format::Dotenv,
+        DEFAULT_ENVIRONMENT,
+        true,
+    )
+    .await
+    .unwrap();
+    assert_eq!(read(&out), "SECOND=2\n");
+
+    env.stop().await;
+}
+
+#[actix_web::test]
+async fn sharing_an_unknown_project_is_refused() {
+    let env = TestEnv::start().await;
+    let _ws = Workstation::new();
+
+    assert!(share(&env.api_url, "missing", false, None, None, None)
+        .await
+        .unwrap()
+        .is_none());
+
+    env.stop().await;
+}
+
+#[actix_web::test]
+async fn single_variables_can_be_read_and_edited_remotely() {
+    let env = TestEnv::start().await;
+    let ws = Workstation::new();
+
+    let env_file = ws.write(".env", "# service settings\nexport A=1\nB=\"two words\"\n");
+    push(
+        &env.api_url,
+        "service",
+        &env_file,
+        Format::Dotenv,
+        DEFAULT_ENVIRONMENT,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+        get_var(&env.api_url, "service", "B", DEFAULT_ENVIRONMENT)
+            .await
+            .unwrap(),
+        Some("two words".to_string())
+    );
+    assert!(
+        get_var(&env.api_url, "service", "MISSING", DEFAULT_ENVIRONMENT)
+            .await
+            .is_err()
+    );
+
+    set_var(&env.api_url, "service", "A", "changed", DEFAULT_ENVIRONMENT)
+        .await
+        .unwrap();
+    set_var(
+        &env.api_url,
+        "service",
+        "C",
+        "multi\nline",
+        DEFAULT_ENVIRONMENT,
+    )
+    .await
+    .unwrap();
+    unset_var(&env.api_url, "service", "B", DEFAULT_ENVIRONMENT)
+        .await
+        .unwrap();
+    assert!(unset_var(&env.api_url, "service", "B", DEFAULT_ENVIRONMENT)
+        .await
+        .is_err());
+    assert!(set_var(
+        &env.api_url,
+        "service",
+        "NOT VALID",
+        "x",
+        DEFAULT_ENVIRONMENT
+    )
+    .await
+    .is_err());
+
+    let out = ws.path("pulled.env");
+    pull(
+        &env.api_url,
+        "service",
+        out.to_str().unwrap(),
+        false,
+        Format::Dotenv,
+        DEFAULT_ENVIRONMENT,
+        true,
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        read(&out),
+        "# service settings\nexport A=changed\nC=\"multi\\nline\"\n"
+    );
+    // The local file was never touched.
+    assert_eq!(
+        read(&env_file),
+        "# service settings\nexport A=1\nB=\"two words\"\n"
+    );
+
+    env.stop().await;
+}
+
+#[actix_web::test]
+async fn diff_compares_the_server_copy_with_a_local_file() {
+    let env = TestEnv::start().await;
+    let ws = Workstation::new();
+
+    let env_file = ws.write(".env", "A=1\n");
+    push(
+        &env.api_url,
+        "service",
+        &env_file,
+        Format::Dotenv,
+        DEFAULT_ENVIRONMENT,
+    )
+    .await
+    .unwrap();
+    assert!(!diff(
+        &env.api_url,
+        "service",
+        &env_file,
+        false,
+        DEFAULT_ENVIRONMENT,
+        true
+    )
+    .await
+    .unwrap());
+
+    ws.write(".env", "A=2\n");
+    assert!(diff(
+        &env.api_url,
+        "service",
+        &env_file,
+        false,
+        DEFAULT_ENVIRONMENT,
+        true
+    )
+    .await
+    .unwrap());
+
+    env.stop().await;
+}
+
+#[cfg(unix)]
+#[actix_web::test]
+async fn run_injects_variables_and_passes_on_the_exit_code() {
+    let env = TestEnv::start().await;
+    let ws = Workstation::new();
+
+    let env_file = ws.write(".env", "CODE=7\nDOTENVPULL_TEST_EXISTING=remote\n");
+    push(
+        &env.api_url,
+        "service",
+        &env_file,
+        Format::Dotenv,
+        DEFAULT_ENVIRONMENT,
+    )
+    .await
+    .unwrap();
+    std::env::set_var("DOTENVPULL_TEST_EXISTING", "local");
+
+    let sh = |script: &str| vec!["sh".to_string(), "-c".to_string(), script.to_string()];
+    let check = |expected: &str| {
+        sh(&format!(
+            "test \"$DOTENVPULL_TEST_EXISTING\" = {} && exit $CODE",
+            expected
+        ))
+    };
+    assert_eq!(
+        run(
+            &env.api_url,
+            "service",
+            &check("remote"),
+            true,
+            DEFAULT_ENVIRONMENT
+        )
+        .await
+        .unwrap(),
+        Some(7)
+    );
+    assert_eq!(
+        run(
+            &env.api_url,
+            "service",
+            &check("local"),
+            false,
+            DEFAULT_ENVIRONMENT
+        )
+        .await
+        .unwrap(),
+        Some(7)
+    );
+    assert_eq!(
+        run(
+            &env.api_url,
+            "service",
+            &sh("kill -TERM $$"),
+            true,
+            DEFAULT_ENVIRONMENT
+        )
+        .await
+        .unwrap(),
+        Some(128 + 15)
+    );
+    assert!(run(
+        &env.api_url,
+        "service",
+        &["no-such-command-xyz".to_string()],
+        true,
+        DEFAULT_ENVIRONMENT
+    )
+    .await
+    .is_err());
+
+    std::env::remove_var("DOTENVPULL_TEST_EXISTING");
+    env.stop().await;
+}
+
+#[actix_web::test]
+async fn pull_and_export_can_convert_the_format() {
+    let env = TestEnv::start().await;
+    let ws = Workstation::new();
+
+    let env_file = ws.write(".env", "# comment\nA=1\n");
+    push(
+        &env.api_url,
+        "service",
+        &env_file,
+        Format::Dotenv,
+        DEFAULT_ENVIRONMENT,
+    )
+    .await
+    .unwrap();
+
+    let out = ws.path("env.json");
+    pull(
+        &env.api_url,
+        "service",
+        out.to_str().unwrap(),
+        false,
+        Format::Json,
+        DEFAULT_ENVIRONMENT,
+        true,
+    )
+    .await
+    .unwrap();
+    assert_eq!(read(&out), "{\n  \"A\": \"1\"\n}\n");
+
+    let out = ws.path("env.sh");
+    export(
+        &env.api_url,
+        "service",
+        Format::Shell,
+        out.to_str(),
+        DEFAULT_ENVIRONMENT,
+    )
+    .await
+    .unwrap();
+    assert_eq!(read(&out), "export A='1'\n");
+
+    env.stop().await;
+}
+
+#[actix_web::test]
+async fn push_and_update_can_import_other_formats() {
+    let env = TestEnv::start().await;
+    let ws = Workstation::new();
+
+    let json_file = ws.write("env.json", "{\"A\": \"1\", \"B\": true}");
+    push(
+        &env.api_url,
+        "service",
+        &json_file,
+        Format::Json,
+        DEFAULT_ENVIRONMENT,
+    )
+    .await
+    .unwrap();
+    let out = ws.path("pulled.env");
+    pull(
+        &env.api_url,
+        "service",
+        out.to_str().unwrap(),
+        false,
+        Format::Dotenv,
+        DEFAULT_ENVIRONMENT,
+        true,
+    )
+    .await
+    .unwrap();
+    assert_eq!(read(&out), "A=1\nB=true\n");
+
+    let secret_file = ws.write(
+        "secret.yaml",
+        "apiVersion: v1\nkind: Secret\ndata:\n  A: Mg==\n",
+    );
+    update(
+        &env.api_url,
+        "service",
+        &secret_file,
+        false,
+        Format::K8sSecret,
+        DEFAULT_ENVIRONMENT,
+        true,
+    )
+    .await
+    .unwrap();
+    pull(
+        &env.api_url,
+        "service",
+        out.to_str().unwrap(),
+        true,
+        Format::Dotenv,
+        DEFAULT_ENVIRONMENT,
+        true,
+    )
+    .await
+    .unwrap();
+    assert_eq!(read(&out), "A=2\n");
+
+    env.stop().await;
+}
+
+#[actix_web::test]
+async fn environments_are_kept_apart_within_a_project() {
+    let env = TestEnv::start().await;
+    let ws = Workstation::new();
+
+    let dev_file = ws.write("dev.env", "STAGE=dev\n");
+    let prod_file = ws.write("prod.env", "STAGE=prod\n");
+    push(&env.api_url, "service", &dev_file, Format::Dotenv, "dev")
+        .await
+        .unwrap();
+    push(&env.api_url, "service", &prod_file, Format::Dotenv, "prod")
+        .await
+        .unwrap();
+    let config = load_config().unwrap();
+    assert_eq!(config.projects["service"].revision("dev"), Some(1));
+    assert_eq!(config.projects["service"].revision("prod"), Some(1));
+
+    // Each environment has its own revision, so updating one doesn't make the other stale.
+    ws.write("prod.env", "STAGE=prod\nDEBUG=0\n");
+    update(
+        &env.api_url,
+        "service",
+        &prod_file,
+        false,
+        Format::Dotenv,
+        "prod",
+        true,
+    )
+    .await
+    .unwrap();
+    ws.write("dev.env", "STAGE=dev\nDEBUG=1\n");
+    update(
+        &env.api_url,
+        "service",
+        &dev_file,
+        false,
+        Format::Dotenv,
+        "dev",
+        true,
+    )
+    .await
+    .unwrap();
+
+    for (environment, expected) in [
+        ("dev", "STAGE=dev\nDEBUG=1\n"),
+        ("prod", "STAGE=prod\nDEBUG=0\n"),
+    ] {
+        let out = ws.path(&format!("{}.pulled", environment));
+        pull(
+            &env.api_url,
+            "service",
+            out.to_str().unwrap(),
+            false,
+            Format::Dotenv,
+            environment,
+            true,
+        )
+        .await
+        .unwrap();
+        assert_eq!(read(&out), expected);
+    }
+
+    delete(&env.api_url, "service", Some("dev")).await.unwrap();
+    let out = ws.path("dev.deleted");
+    pull(
+        &env.api_url,
+        "service",
+        out.to_str().unwrap(),
+        false,
+        Format::Dotenv,
+        "dev",
+        true,
+    )
+    .await
+    .unwrap();
+    assert!(!out.exists());
+    assert_eq!(
+        load_config().unwrap().projects["service"].revision("dev"),
+        None
+    );
+    assert_eq!(
+        get_var(&env.api_url, "service", "STAGE", "prod")
+            .await
+            .unwrap(),
+        Some("prod".to_string())
+    );
+
+    delete(&env.api_url, "service", None).await.unwrap();
+    assert!(!load_config().unwrap().projects.contains_key("service"));
+
+    env.stop().await;
+}
+
+#[actix_web::test]
+async fn pushing_after_the_last_environment_is_deleted_creates_the_project_again() {
+    let env = TestEnv::start().await;
+    let ws = Workstation::new();
+
+    let dev_file = ws.write("dev.env", "STAGE=dev\n");
+    push(&env.api_url, "service", &dev_file, Format::Dotenv, "dev")
+        .await
+        .unwrap();
+    let before = load_config().unwrap().projects["service"].clone();
+    delete(&env.api_url, "service", Some("dev")).await.unwrap();
+    assert!(load_config().unwrap().projects.contains_key("service"));
+
+    push(&env.api_url, "service", &dev_file, Format::Dotenv, "dev")
+        .await
+        .unwrap();
+    let after = load_config().unwrap().projects["service"].clone();
+    assert_ne!(after.access_key, before.access_key);
+    assert_eq!(after.encryption_key, before.encryption_key);
+    assert_eq!(after.revision("dev"), Some(1));
+    assert_eq!(
+        get_var(&env.api_url, "service", "STAGE", "dev")
+            .await
+            .unwrap(),
+        Some("dev".to_string())
+    );
+
+    env.stop().await;
+}
+
+#[actix_web::test]
+async fn environments_can_be_layered_over_a_base() {
+    let env = TestEnv::start().await;
+    let ws = Workstation::new();
+
+    let base_file = ws.write("base.env", "# shared\nDB_HOST=localhost\nLOG_LEVEL=debug\n");
+    let prod_file = ws.write("prod.env", "LOG_LEVEL=warn\nREPLICAS=3\n");
+    push(&env.api_url, "service", &base_file, Format::Dotenv, "base")
+        .await
+        .unwrap();
+    push(&env.api_url, "service", &prod_file, Format::Dotenv, "prod")
+        .await
+        .unwrap();
+    set_base(&env.api_url, "service", Some("base"))
+        .await
+        .unwrap();
+
+    let out = ws.path("prod.pulled");
+    pull(
+        &env.api_url,
+        "service",
+        out.to_str().unwrap(),
+        false,
+        Format::Dotenv,
+        "prod",
+        true,
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        read(&out),
+        "# shared\nDB_HOST=localhost\nLOG_LEVEL=warn\nREPLICAS=3\n"
+    );
+    // The pulled file matches the layered environment, not the environment alone.
+    let prod_pulled = out.to_str().unwrap();
+    assert!(
+        !diff(&env.api_url, "service", prod_pulled, false, "prod", true)
+            .await
+            .unwrap()
+    );
+    assert!(
+        diff(&env.api_url, "service", prod_pulled, false, "prod", false)
+            .await
+            .unwrap()
+    );
+    assert_eq!(
+        get_var(&env.api_url, "service", "DB_HOST", "prod")
+            .await
+            .unwrap(),
+        Some("localhost".to_string())
+    );
+
+    // A change to the base shows up in every environment layered over it.
+    set_var(&env.api_url, "service", "DB_HOST", "db.internal", "base")
+        .await
+        .unwrap();
+    pull(
+        &env.api_url,
+        "service",
+        out.to_str().unwrap(),
+        true,
+        Format::Dotenv,
+        "prod",
+        true,
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        read(&out),
+        "# shared\nDB_HOST=db.internal\nLOG_LEVEL=warn\nREPLICAS=3\n"
+    );
+
+    pull(
+        &env.api_url,
+        "service",
+        out.to_str().unwrap(),
+        true,
+        Format::Dotenv,
+        "prod",
+        false,
+    )
+    .await
+    .unwrap();
+    assert_eq!(read(&out), "LOG_LEVEL=warn\nREPLICAS=3\n");
+
+    set_base(&env.api_url, "service", None).await.unwrap();
+    assert_eq!(
+        get_var(&env.api_url, "service", "DB_HOST", "prod")
+            .await
+            .ok()
+            .flatten(),
+        None
+    );
+
+    env.stop().await;
+}
+
+#[actix_web::test]
+async fn layered_environments_are_only_updated_with_their_own_variables() {
+    let env = TestEnv::start().await;
+    let ws = Workstation::new();
+
+    let base_file = ws.write("base.env", "DB_HOST=localhost\nLOG_LEVEL=debug\n");
+    let prod_file = ws.write("prod.env", "LOG_LEVEL=warn\n");
+    push(&env.api_url, "service", &base_file, Format::Dotenv, "base")
+        .await
+        .unwrap();
+    push(&env.api_url, "service", &prod_file, Format::Dotenv, "prod")
+        .await
+        .unwrap();
+    let link = share(&env.api_url, "service", false, None, None, None)
+        .await
+        .unwrap()
+        .unwrap();
+    set_base(&env.api_url, "service", Some("base"))
+        .await
+        .unwrap();
+
+    // Uploading a layered pull would copy the base's variables into prod.
+    let out = ws.path("prod.pulled");
+    let pull_prod = |with_base: bool, force: bool| {
+        pull(
+            &env.api_url,
+            "service",
+            out.to_str().unwrap(),
+            force,
+            Format::Dotenv,
+            "prod",
+            with_base,
+        )
+    };
+    pull_prod(true, false).await.unwrap();
+    assert_eq!(read(&out), "DB_HOST=localhost\nLOG_LEVEL=warn\n");
+    fs::write(&out, "DB_HOST=localhost\nLOG_LEVEL=error\n").unwrap();
+    let layered = out.to_str().unwrap();
+    update(
+        &env.api_url,
+        "service",
+        layered,
+        false,
+        Format::Dotenv,
+        "prod",
+        true,
+    )
+    .await
+    .unwrap();
+    pull_prod(false, true).await.unwrap();
+    assert_eq!(read(&out), "LOG_LEVEL=warn\n");
+
+    fs::write(&out, "LOG_LEVEL=error\n").unwrap();
+    update(
+        &env.api_url,
+        "service",
+        out.to_str().unwrap(),
+        false,
+        Format::Dotenv,
+        "prod",
+        false,
+    )
+    .await
+    .unwrap();
+    set_var(&env.api_url, "service", "DB_HOST", "db.internal", "base")
+        .await
+        .unwrap();
+    pull_prod(true, true).await.unwrap();
+    assert_eq!(read(&out), "DB_HOST=db.internal\nLOG_LEVEL=error\n");
+
+    // The base is declared on the server, so a teammate sees it without being told.
+    let teammate = Workstation::new();
+    getshared(
+        &link.api_url,
+        &link.share_code,
+        &link.project_id,
+        &link.encryption_key,
+        None,
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        get_base(&env.api_url, "service").await.unwrap(),
+        Some("base".to_string())
+    );
+    assert_eq!(
+        get_var(&env.api_url, "service", "DB_HOST", "prod")
+            .await
+            .unwrap(),
+        Some("db.internal".to_string())
+    );
+    drop(teammate);
+    ws.enter();
+
+    // Deleting the base environment removes the declaration with it.
+    delete(&env.api_url, "service", Some("base")).await.unwrap();
+    assert_eq!(get_base(&env.api_url, "service").await.unwrap(), None);
+
+    env.stop().await;
+}
+
+#[actix_web::test]
+async fn directories_are_pushed_and_pulled_as_one_bundle() {
+    let env = TestEnv::start().await;
+    let ws = Workstation::new();
+
+    ws.write("service/.env", "A=1\n");
+    ws.write("service/config/local.toml", "port = 8080\n");
+    let source = ws.path("service");
+    push(
+        &env.api_url,
+        "service",
+        source.to_str().unwrap(),
+        Format::Dotenv,
+        DEFAULT_ENVIRONMENT,
+    )
+    .await
+    .unwrap();
+
+    let out = ws.path("restored");
+    pull(
+        &env.api_url,
+        "service",
+        out.to_str().unwrap(),
+        false,
+        Format::Dotenv,
+        DEFAULT_ENVIRONMENT,
+        true,
+    )
+    .await
+    .unwrap();
+    assert_eq!(read(out.join(".env")), "A=1\n");
+    assert_eq!(read(out.join("config/local.toml")), "port = 8080\n");
+
+    // Existing files are left alone without --force.
+    ws.write("service/.env", "A=2\n");
+    let pattern = format!("{}/**/*.env", source.display());
+    update(
+        &env.api_url,
+        "service",
+        &pattern,
+        false,
+        Format::Dotenv,
+        DEFAULT_ENVIRONMENT,
+        true,
+    )
+    .await
+    .unwrap();
+    pull(
+        &env.api_url,
+        "service",
+        out.to_str().unwrap(),
+        false,
+        Format::Dotenv,
+        DEFAULT_ENVIRONMENT,
+        true,
+    )
+    .await
+    .unwrap();
+    assert_eq!(read(out.join(".env")), "A=1\n");
+    pull(
+        &env.api_url,
+        "service",
+        out.to_str().unwrap(),
+        true,
+        Format::Dotenv,
+        DEFAULT_ENVIRONMENT,
+        true,
+    )
+    .await
+    .unwrap();
+    assert_eq!(read(out.join(".env")), "A=2\n");
+
+    env.stop().await;
+}
+
+#[actix_web::test]
+async fn binary_files_are_pulled_back_byte_for_byte() {
+    let env = TestEnv::start().await;
+    let ws = Workstation::new();
+
+    let keystore: Vec<u8> = vec![0xfe, 0xed, 0xfe, 0xed, 0, 0, 0, 2, 0x9f, 0x92, 0x96, 0xff];
+    let source = ws.path("keystore.jks");
+    fs::write(&source, &keystore).unwrap();
+    push(
+        &env.api_url,
+        "certs",
+        source.to_str().unwrap(),
+        Format::Dotenv,
+        DEFAULT_ENVIRONMENT,
+    )
+    .await
+    .unwrap();
+
+    let out = ws.path("pulled.jks");
+    pull(
+        &env.api_url,
+        "certs",
+        out.to_str().unwrap(),
+        false,
+        Format::Dotenv,
+        DEFAULT_ENVIRONMENT,
+        true,
+    )
+    .await
+    .unwrap();
+    assert_eq!(fs::read(&out).unwrap(), keystore);
+
+    // Text-only operations refuse binary content instead of mangling it.
+    assert!(export(
+        &env.api_url,
+        "certs",
+        Format::Json,
+        None,
+        DEFAULT_ENVIRONMENT
+    )
+    .await
+    .is_err());
+    assert!(!diff(
+        &env.api_url,
+        "certs",
+        source.to_str().unwrap(),
+        false,
+        DEFAULT_ENVIRONMENT,
+        true
+    )
+    .await
+    .unwrap());
+
+    env.stop().await;
+}
+
+#[actix_web::test]
+async fn pull_with_the_wrong_key_fails_cleanly() {
+    let env = TestEnv::start().await;
+    let ws = Workstation::new();
+
+    let env_file = ws.write(".env", "A=1\n");
+    push(
+        &env.api_url,
+        "service",
+        &env_file,
+        Format::Dotenv,
+        DEFAULT_ENVIRONMENT,
+    )
+    .await
+    .unwrap();
+    let mut config = load_config().unwrap();
+    config.project_mut("service").unwrap().encryption_key =
+        "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=".to_string();
+    save_config(&config).unwrap();
+
+    let out = ws.path("pulled.env");
+    pull(
+        &env.api_url,
+        "service",
+        out.to_str().unwrap(),
+        false,
+        Format::Dotenv,
+        DEFAULT_ENVIRONMENT,
+        true,
+    )
+    .await
+    .unwrap();
+    assert!(!out.exists());
+
+    env.stop().await;
+}
+
+#[actix_web::test]
+async fn malformed_server_responses_are_errors() {
+    async fn malformed() -> HttpResponse {
+        HttpResponse::Ok().json(serde_json::json!({ "encrypted_content": 5 }))
+    }
+    let _guard = CWD_LOCK.lock().await;
+    let server = HttpServer::new(|| {
+        App::new()
+            .route("/push", web::post().to(malformed))
+            .route("/pull", web::get().to(malformed))
+            .route("/share", web::get().to(malformed))
+    })
+    .workers(1)
+    .bind(("127.0.0.1", 0))
+    .unwrap();
+    let api_url = format!("http://{}", server.addrs()[0]);
+    let server = server.run();
+    let handle = server.handle();
+    actix_web::rt::spawn(server);
+    let ws = Workstation::new();
+
+    let env_file = ws.write(".env", "A=1\n");
+    let err = push(
+        &api_url,
+        "service",
+        &env_file,
+        Format::Dotenv,
+        DEFAULT_ENVIRONMENT,
+    )
+    .await
+    .unwrap_err();
+    assert!(err.to_string().contains("access key"), "{}", err);
+
+    update_config(
+        "service",
+        &api_url,
+        "ak",
+        "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=",
+    )
+    .unwrap();
+    let out = ws.path("pulled.env");
+    let err = pull(
+        &api_url,
+        "service",
+        out.to_str().unwrap(),
+        false,
+        Format::Dotenv,
+        DEFAULT_ENVIRONMENT,
+        false,
+    )
+    .await
+    .unwrap_err();
+    assert!(err.to_string().contains("No content found"), "{}", err);
+
+    let err = getshared(&api_url, "code", "service", "key", None)
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("No content found"), "{}", err);
+
+    handle.stop(true).await;
+}
+
+/// Rotates both keys of a two-environment project and checks the old keys stop working.
+async fn rotate_key_scenario(env: TestEnv) {
+    let ws = Workstation::new();
+
+    let env_file = ws.write(".env", "A=dev\n");
+    push(
+        &env.api_url,
+        "service",
+        &env_file,
+        Format::Dotenv,
+        DEFAULT_ENVIRONMENT,
+    )
+    .await
+    .unwrap();
+    ws.write(".env", "A=prod\n");
+    push(&env.api_url, "service", &env_file, Format::Dotenv, "prod")
+        .await
+        .unwrap();
+    let old_config = read("dotenvpull_config.json");
+
+    rotate_key(&env.api_url, "service", true).await.unwrap();
+    let config = load_config().unwrap();
+    let old = Config::parse(&old_config).unwrap();
+    let (project, old_project) = (&config.projects["service"], &old.projects["service"]);
+    assert_ne!(project.access_key, old_project.access_key);
+    assert_ne!(project.encryption_key, old_project.encryption_key);
+    assert_eq!(project.pending_encryption_key, None);
+
+    for (environment, expected) in [(DEFAULT_ENVIRONMENT, "A=dev\n"), ("prod", "A=prod\n")] {
+        let out = ws.path(&format!("{}.env", environment));
+        pull(
+            &env.api_url,
+            "service",
+            out.to_str().unwrap(),
+            false,
+            Format::Dotenv,
+            environment,
+            true,
+        )
+        .await
+        .unwrap();
+        assert_eq!(read(&out), expected);
+    }
+
+    // Someone still holding the old config can no longer pull.
+    let departed = Workstation::new();
+    departed.write("dotenvpull_config.json", &old_config);
+    let out = departed.path("pulled.env");
+    pull(
+        &env.api_url,
+        "service",
+        out.to_str().unwrap(),
+        false,
+        Format::Dotenv,
+        DEFAULT_ENVIRONMENT,
+        true,
+    )
+    .await
+    .unwrap();
+    assert!(!out.exists());
+
+    env.stop().await;
+}
+
+#[actix_web::test]
+async fn rotate_key_re_encrypts_every_environment() {
+    rotate_key_scenario(TestEnv::start().await).await;
+}
+
+#[actix_web::test]
+async fn rotate_key_with_sqlite_storage() {
+    let db_dir = tempfile::tempdir().unwrap();
+    let db_path = db_dir.path().join("dotenvpull.db");
+    let storage = SqliteStorage::open(db_path.to_str().unwrap()).unwrap();
+    rotate_key_scenario(TestEnv::start_with(Arc::new(storage)).await).await;
+}
+
+#[actix_web::test]
+async fn interrupted_rotation_can_be_resumed() {
+    let env = TestEnv::start().await;
+    let ws = Workstation::new();
+    let env_file = ws.write(".env", "A=1\n");
+    push(
+        &env.api_url,
+        "service",
+        &env_file,
+        Format::Dotenv,
+        DEFAULT_ENVIRONMENT,
+    )
+    .await
+    .unwrap();
+    let before = load_config().unwrap();
+
+    // A rotation that never reached the server is dropped.
+    let mut config = before.clone();
+    let project = config.projects.get_mut("service").unwrap();
+    project.pending_encryption_key = Some(general_purpose::STANDARD.encode([3u8; 32]));
+    project.pending_access_key = Some("f".repeat(64));
+    save_config(&config).unwrap();
+    assert!(rotate_key(&env.api_url, "service", false).await.is_err());
+    resume_rotation(&env.api_url, "service").await.unwrap();
+    assert_eq!(load_config().unwrap(), before);
+
+    // One the server went through with, but whose result was never saved, is finished.
+    rotate_key(&env.api_url, "service", true).await.unwrap();
+    let rotated = load_config().unwrap();
+    let mut config = before.clone();
+    let project = config.projects.get_mut("service").unwrap();
+    project.pending_encryption_key = Some(rotated.projects["service"].encryption_key.clone());
+    project.pending_access_key = Some(rotated.projects["service"].access_key.clone());
+    save_config(&config).unwrap();
+    resume_rotation(&env.api_url, "service").await.unwrap();
+    assert_eq!(load_config().unwrap(), rotated);
+    let out = ws.path("pulled.env");
+    pull(
+        &env.api_url,
+        "service",
+        out.to_str().unwrap(),
+        false,
+        Format::Dotenv,
+        DEFAULT_ENVIRONMENT,
+        true,
+    )
+    .await
+    .unwrap();
+    assert_eq!(read(&out), "A=1\n");
+    assert!(resume_rotation(&env.api_url, "service").await.is_err());
+
+    env.stop().await;
+}
+
+#[actix_web::test]
+async fn locked_config_keeps_keys_encrypted_at_rest() {
+    let env = TestEnv::start().await;
+    let ws = Workstation::new();
+
+    let env_file = ws.write(".env", "A=1\n");
+    push(
+        &env.api_url,
+        "service",
+        &env_file,
+        Format::Dotenv,
+        DEFAULT_ENVIRONMENT,
+    )
+    .await
+    .unwrap();
+    let encryption_key = load_config().unwrap().projects["service"]
+        .encryption_key
+        .clone();
+    lock_config("correct horse", 0).unwrap();
+    let raw = read("dotenvpull_config.json");
+    assert!(!raw.contains(&encryption_key));
+    assert_eq!(
+        load_config().unwrap().projects["service"].encryption_key,
+        encryption_key
+    );
+
+    // Once forgotten, the passphrase is needed again.
+    forget_passphrase().unwrap();
+    std::env::set_var("DOTENVPULL_PASSPHRASE", "wrong");
+    assert!(load_config().is_err());
+
+    // Commands that don't need the keys work without the passphrase and leave them intact.
+    let raw = read("dotenvpull_config.json");
+    set_server("team", "http://team.invalid", None).unwrap();
+    assert!(lock_config("other", 0)
+        .unwrap_err()
+        .to_string()
+        .contains("already locked"));
+    forget_passphrase().unwrap();
+    let locked = load_config_locked().unwrap();
+    assert!(locked.servers.contains_key("team"));
+    assert_eq!(
+        locked.projects["service"].encryption_key,
+        Config::parse_locked(&raw).unwrap().projects["service"].encryption_key
+    );
+    std::env::set_var("DOTENVPULL_PASSPHRASE", "correct horse");
+    let out = ws.path("pulled.env");
+    pull(
+        &env.api_url,
+        "service",
+        out.to_str().unwrap(),
+        false,
+        Format::Dotenv,
+        DEFAULT_ENVIRONMENT,
+        true,
+    )
+    .await
+    .unwrap();
+    assert_eq!(read(&out), "A=1\n");
+    assert!(!read("dotenvpull_config.json").contains(&encryption_key));
+
+    remove_config_lock().unwrap();
+    std::env::remove_var("DOTENVPULL_PASSPHRASE");
+    assert!(read("dotenvpull_config.json").contains(&encryption_key));
+
+    env.stop().await;
+}
+
+#[actix_web::test]
+async fn config_is_discovered_without_being_created() {
+    let env = TestEnv::start().await;
+    let ws = Workstation::new();
+    std::env::remove_var(CONFIG_ENV);
+    std::env::set_var("XDG_CONFIG_HOME", ws.path("xdg"));
+    let global = ws.path("xdg/dotenvpull/config");
+    let nested = ws.path("repo/sub/dir");
+    fs::create_dir_all(&nested).unwrap();
+    std::env::set_current_dir(&nested).unwrap();
+
+    // Without any config, reading one writes nothing, and the first write goes global.
+    assert_eq!(config_path(), global);
+    assert!(load_config().unwrap().projects.is_empty());
+    assert!(history(&env.api_url, "service", DEFAULT_ENVIRONMENT)
+        .await
+        .is_err());
+    assert!(!global.exists());
+    let env_file = ws.write(".env", "A=1\n");
+    push(
+        &env.api_url,
+        "global",
+        &env_file,
+        Format::Dotenv,
+        DEFAULT_ENVIRONMENT,
+    )
+    .await
+    .unwrap();
+    assert!(load_config().unwrap().projects.contains_key("global"));
+    assert!(global.exists());
+
+    // A project-local config in a parent directory wins over the global one.
+    let local = ws.write("repo/dotenvpull_config.json", "{}");
+    assert_eq!(config_path(), local.as_ref() as &Path);
+    push(
+        &env.api_url,
+        "local",
+        &env_file,
+        Format::Dotenv,
+        DEFAULT_ENVIRONMENT,
+    )
+    .await
+    .unwrap();
+    let config = load_config().unwrap();
+    assert!(config.projects.contains_key("local"));
+    assert!(!config.projects.contains_key("global"));
+
+    // `--config` and `DOTENVPULL_CONFIG` name the file outright.
+    std::env::set_var(CONFIG_ENV, &global);
+    assert!(load_config().unwrap().projects.contains_key("global"));
+    let explicit = ws.path("explicit.json");
+    set_config_path(Some(&explicit));
+    assert_eq!(config_path(), explicit);
+    set_config_path(None);
+
+    std::env::remove_var("XDG_CONFIG_HOME");
+    env.stop().await;
+}
+
+#[actix_web::test]
+async fn projects_remember_the_server_they_live_on() {
+    let env = TestEnv::start().await;
+    let alice = Workstation::new();
+    set_server("team", &env.api_url, None).unwrap();
+
+    // A new project goes to the profile given, and stays there.
+    let config = load_config().unwrap();
+    let api_url = config
+        .resolve_api_url(Some("service"), Some("team"))
+        .unwrap()
+        .to_string();
+    assert_eq!(api_url, env.api_url);
+    let env_file = alice.write(".env", "A=1\n");
+    push(
+        &api_url,
+        "service",
+        &env_file,
+        Format::Dotenv,
+        DEFAULT_ENVIRONMENT,
+    )
+    .await
+    .unwrap();
+    let config = load_config().unwrap();
+    assert_eq!(config.projects["service"].server.as_deref(), Some("team"));
+    assert_eq!(
+        config.resolve_api_url(Some("service"), None).unwrap(),
+        env.api_url
+    );
+    assert!(config
+        .resolve_api_url(Some("service"), Some(DEFAULT_SERVER))
+        .is_err());
+    assert_ne!(
+        config.resolve_api_url(Some("other"), None).unwrap(),
+        env.api_url
+    );
+    assert!(config.resolve_api_url(None, Some("missing")).is_err());
+
+    // Changing the default profile doesn't move existing projects.
+    set_default_server("team").unwrap();
+    set_server("personal", "http://personal.invalid", None).unwrap();
+    set_default_server("personal").unwrap();
+    assert_eq!(
+        load_config()
+            .unwrap()
+            .resolve_api_url(Some("service"), None)
+            .unwrap(),
+        env.api_url
+    );
+
+    // A recipient of a single project gets a profile for the server it was shared from.
+    let credentials = share(&api_url, "service", false, None, None, None)
+        .await
+        .unwrap()
+        .unwrap();
+    let bob = Workstation::new();
+    getshared(
+        &credentials.api_url,
+        &credentials.share_code,
+        "service",
+        &credentials.encryption_key,
+        None,
+    )
+    .await
+    .unwrap();
+    let config = load_config().unwrap();
+    let server = config.projects["service"].server.clone().unwrap();
+    assert_ne!(server, DEFAULT_SERVER);
+    assert_eq!(config.server_url(&server).unwrap(), env.api_url);
+    let out = bob.path("pulled.env");
+    pull(
+        config.resolve_api_url(Some("service"), None).unwrap(),
+        "service",
+        out.to_str().unwrap(),
+        false,
+        Format::Dotenv,
+        DEFAULT_ENVIRONMENT,
+        true,
+    )
+    .await
+    .unwrap();
+    assert_eq!(read(&out), "A=1\n");
+
+    env.stop().await;
+}
+
+#[actix_web::test]
+async fn server_tokens_are_sent_to_their_profile() {
+    let env = TestEnv::start_with_token("team-token").await;
+    let ws = Workstation::new();
+
+    let env_file = ws.write(".env", "A=1\n");
+    set_server("team", &env.api_url, None).unwrap();
+    push(&env.api_url, "service", &env_file, Format::Dotenv, "dev")
+        .await
+        .unwrap();
+    assert!(!load_config().unwrap().projects.contains_key("service"));
+
+    set_server("team", &env.api_url, Some("team-token")).unwrap();
+    push(&env.api_url, "service", &env_file, Format::Dotenv, "dev")
+        .await
+        .unwrap();
+    assert_eq!(
+        get_var(&env.api_url, "service", "A", "dev").await.unwrap(),
+        Some("1".to_string())
+    );
+
+    // The token is credentials, so it is encrypted along with the keys.
+    lock_config("correct horse", 0).unwrap();
+    assert!(!read("dotenvpull_config.json").contains("team-token"));
+    assert_eq!(
+        get_var(&env.api_url, "service", "A", "dev").await.unwrap(),
+        Some("1".to_string())
+    );
+    remove_config_lock().unwrap();
+    assert_eq!(
+        load_config().unwrap().servers["team"].token.as_deref(),
+        Some("team-token")
+    );
+
+    env.stop().await;
+}
+
+#[cfg(unix)]
+#[actix_web::test]
+async fn secret_files_are_written_private_and_whole() {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+
+    let env = TestEnv::start().await;
+    let ws = Workstation::new();
+    let env_file = ws.write(".env", "SECRET=1\n");
+    push(
+        &env.api_url,
+        "service",
+        &env_file,
+        Format::Dotenv,
+        DEFAULT_ENVIRONMENT,
+    )
+    .await
+    .unwrap();
+    assert_eq!(mode(&ws.path("dotenvpull_config.json")), 0o600);
+
+    let pull_into = |out: PathBuf| {
+        let api_url = env.api_url.clone();
+        async move {
+            pull(
+                &api_url,
+                "service",
+                out.to_str().unwrap(),
+                true,
+                Format::Dotenv,
+                DEFAULT_ENVIRONMENT,
+                true,
+            )
+            .await
+            .unwrap();
+            out
+        }
+    };
+
+    let out = pull_into(ws.path("new.env")).await;
+    assert_eq!(mode(&out), 0o600);
+
+    // A looser mode is tightened, a tighter one is kept.
+    let loose = ws.path("loose.env");
+    fs::write(&loose, "OLD=1\n").unwrap();
+    fs::set_permissions(&loose, fs::Permissions::from_mode(0o644)).unwrap();
+    pull_into(loose.clone()).await;
+    assert_eq!(mode(&loose), 0o600);
+    let tight = ws.path("tight.env");
+    fs::write(&tight, "OLD=1\n").unwrap();
+    fs::set_permissions(&tight, fs::Permissions::from_mode(0o400)).unwrap();
+    pull_into(tight.clone()).await;
+    assert_eq!(mode(&tight), 0o400);
+    assert_eq!(read(&tight), "SECRET=1\n");
+
+    // Nothing is left behind next to the files.
+    let mut names: Vec<String> = fs::read_dir(ws.path(""))
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    names.sort();
+    assert_eq!(
+        names,
+        [
+            ".env",
+            "dotenvpull_config.json",
+            "loose.env",
+            "new.env",
+            "tight.env"
+        ]
+    );
+
+    env.stop().await;
+}
+
+#[cfg(unix)]
+#[actix_web::test]
+async fn passphrase_key_is_only_cached_in_the_runtime_directory() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let env = TestEnv::start().await;
+    let ws = Workstation::new();
+    let runtime_dir = std::env::var_os("XDG_RUNTIME_DIR");
+    std::env::remove_var("XDG_RUNTIME_DIR");
+    std::env::set_var("XDG_CONFIG_HOME", ws.path("xdg"));
+
+    // Without a runtime directory the key is kept in memory only.
+    lock_config("correct horse", 60).unwrap();
+    assert!(!ws.path("xdg").exists());
+    assert!(load_config().is_ok());
+    forget_passphrase().unwrap();
+
+    // Expired keys are deleted as soon as the cache is used.
+    std::env::set_var("XDG_RUNTIME_DIR", ws.path("run"));
+    let keys = ws.path("run/dotenvpull");
+    fs::create_dir_all(&keys).unwrap();
+    fs::write(keys.join("expired.key"), "1\nAAAA\n").unwrap();
+    std::env::set_var("DOTENVPULL_PASSPHRASE", "correct horse");
+    load_config().unwrap();
+    let cached: Vec<_> = fs::read_dir(&keys)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    assert_eq!(cached.len(), 1);
+    assert_ne!(cached[0], "expired.key");
+    assert_eq!(
+        fs::metadata(&keys).unwrap().permissions().mode() & 0o777,
+        0o700
+    );
+
+    // Forgetting the passphrase removes the cached key.
+    forget_passphrase().unwrap();
+    assert!(fs::read_dir(&keys).unwrap().next().is_none());
+    remove_config_lock().unwrap();
+    std::env::remove_var("DOTENVPULL_PASSPHRASE");
+
+    std::env::remove_var("XDG_CONFIG_HOME");
+    std::env::remove_var("XDG_RUNTIME_DIR");
+    if let Some(runtime_dir) = runtime_dir {
+        std::env::set_var("XDG_RUNTIME_DIR", runtime_dir);
+    }
+    env.stop().await;
+}
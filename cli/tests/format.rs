@@ -0,0 +1,118 @@
+use cli::format::Format;
+
+const SAMPLE: &str = "# comment\nA=plain\nB=\"it's \\\"quoted\\\" $HOME\"\n";
+
+#[test]
+fn renders_every_format() {
+    let cases = [
+        (Format::Dotenv, "A=plain\nB=\"it's \\\"quoted\\\" $HOME\"\n"),
+        (
+            Format::Shell,
+            "export A='plain'\nexport B='it'\\''s \"quoted\" $HOME'\n",
+        ),
+        (
+            Format::Json,
+            "{\n  \"A\": \"plain\",\n  \"B\": \"it's \\\"quoted\\\" $HOME\"\n}\n",
+        ),
+        (
+            Format::Yaml,
+            "\"A\": \"plain\"\n\"B\": \"it's \\\"quoted\\\" $HOME\"\n",
+        ),
+        (Format::Docker, "A=plain\nB=it's \"quoted\" $HOME\n"),
+        (
+            Format::Systemd,
+            "A=\"plain\"\nB=\"it's \\\"quoted\\\" \\$HOME\"\n",
+        ),
+        (
+            Format::K8sSecret,
+            "apiVersion: v1\nkind: Secret\nmetadata:\n  name: my-service\ntype: Opaque\ndata:\n  \"A\": cGxhaW4=\n  \"B\": aXQncyAicXVvdGVkIiAkSE9NRQ==\n",
+        ),
+    ];
+    for (format, expected) in cases {
+        assert_eq!(
+            format.render(SAMPLE, "My_Service").unwrap(),
+            expected,
+            "{:?}",
+            format
+        );
+    }
+}
+
+#[test]
+fn docker_env_files_cannot_hold_multiline_values() {
+    assert!(Format::Docker.render("A=\"one\\ntwo\"\n", "x").is_err());
+}
+
+#[test]
+fn keys_survive_formats_with_stricter_names() {
+    assert!(Format::Shell.render("a.b=1\n", "x").is_err());
+    assert!(Format::Shell.render("a-b=1\n", "x").is_err());
+
+    // Unquoted, these keys would read back as null, a boolean or a number.
+    let source = "null=1\ntrue=2\nyes=3\n";
+    for format in [Format::Yaml, Format::K8sSecret] {
+        let exported = format.render(source, "svc").unwrap();
+        assert_eq!(format.import(&exported).unwrap(), source, "{:?}", format);
+    }
+}
+
+#[test]
+fn format_names_parse() {
+    for name in Format::NAMES {
+        assert!(name.parse::<Format>().is_ok(), "{}", name);
+    }
+    assert_eq!("k8s-secret".parse(), Ok(Format::K8sSecret));
+    assert!("toml".parse::<Format>().is_err());
+}
+
+#[test]
+fn imports_every_supported_format() {
+    let expected = "A=plain\nB=\"it's \\\"quoted\\\" $HOME\"\n";
+    let cases = [
+        (
+            Format::Shell,
+            "#!/bin/sh\nexport A=plain\nexport B='it'\\''s \"quoted\" $HOME'\n",
+        ),
+        (
+            Format::Json,
+            "{\"A\": \"plain\", \"B\": \"it's \\\"quoted\\\" $HOME\"}",
+        ),
+        (Format::Yaml, "# vars\nA: plain\nB: 'it''s \"quoted\" $HOME'\n"),
+        (
+            Format::K8sSecret,
+            "apiVersion: v1\nkind: Secret\nmetadata:\n  name: x\ndata:\n  A: cGxhaW4=\nstringData:\n  B: \"it's \\\"quoted\\\" $HOME\"\n",
+        ),
+    ];
+    for (format, input) in cases {
+        assert_eq!(format.import(input).unwrap(), expected, "{:?}", format);
+    }
+}
+
+#[test]
+fn exports_import_back_to_the_same_variables() {
+    let source = "A=plain\nB=\"multi\\nline 'quoted' \\\"value\\\"\"\nC=\n";
+    for format in [Format::Shell, Format::Json, Format::Yaml, Format::K8sSecret] {
+        let exported = format.render(source, "svc").unwrap();
+        assert_eq!(format.import(&exported).unwrap(), source, "{:?}", format);
+    }
+}
+
+#[test]
+fn unusable_input_is_rejected() {
+    for (format, input) in [
+        (Format::Json, "[1, 2]"),
+        (Format::Json, "{\"A\": {\"nested\": true}}"),
+        (Format::Json, "{\"not valid\": \"x\"}"),
+        (Format::Yaml, "A: [1, 2]\n"),
+        (Format::K8sSecret, "kind: ConfigMap\ndata:\n  A: b\n"),
+        (
+            Format::K8sSecret,
+            "kind: Secret\ndata:\n  A: \"not base64!\"\n",
+        ),
+        (Format::Shell, "export A='unterminated\n"),
+        (Format::Shell, "echo hello\n"),
+        (Format::Docker, "A=1\n"),
+    ] {
+        assert!(format.import(input).is_err(), "{:?}: {}", format, input);
+    }
+}
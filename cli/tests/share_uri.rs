@@ -0,0 +1,46 @@
+use cli::share_uri::ShareUri;
+
+fn sample(api_url: &str, project_id: &str) -> ShareUri {
+    ShareUri {
+        api_url: api_url.to_string(),
+        project_id: project_id.to_string(),
+        share_code: "++++ECA/".to_string(),
+        encryption_key: "S2V5K2tleS9rZXk=".to_string(),
+    }
+}
+
+#[test]
+fn https_link_round_trips() {
+    let uri = sample("https://env.example.com", "api");
+    let link = uri.to_string();
+    assert_eq!(
+        link,
+        "dotenvpull://env.example.com/api#----ECA_.S2V5K2tleS9rZXk"
+    );
+    assert_eq!(ShareUri::parse(&link).unwrap(), uri);
+}
+
+#[test]
+fn http_link_keeps_port_path_and_odd_project_names() {
+    let uri = sample("http://localhost:8080/dotenv", "my project/#1");
+    let link = uri.to_string();
+    assert!(link.starts_with("dotenvpull+http://localhost:8080/dotenv/my%20project%2F%231#"));
+    assert_eq!(ShareUri::parse(&link).unwrap(), uri);
+}
+
+#[test]
+fn malformed_links_are_rejected() {
+    for link in [
+        "https://env.example.com/api#code.key",
+        "dotenvpull://env.example.com/api",
+        "dotenvpull://env.example.com/api#codeonly",
+        "dotenvpull://api#code.key",
+        "dotenvpull://env.example.com/api#c*de.key",
+    ] {
+        assert!(
+            ShareUri::parse(link).is_err(),
+            "{} should be rejected",
+            link
+        );
+    }
+}